@@ -0,0 +1,106 @@
+use crate::{Device, Error, EzvizApi};
+use futures::{future, lock::Mutex};
+use std::collections::HashMap;
+
+/// Several logged in accounts used as one, e.g. for cameras spread across homes that
+/// belong to different people.
+#[derive(Debug, Default)]
+pub struct EzvizAccounts {
+    accounts: Vec<EzvizApi>,
+    /// Index of the account each device was last listed by.
+    owners: Mutex<HashMap<String, usize>>,
+}
+
+impl From<Vec<EzvizApi>> for EzvizAccounts {
+    fn from(accounts: Vec<EzvizApi>) -> Self {
+        EzvizAccounts {
+            accounts,
+            owners: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl EzvizAccounts {
+    pub fn new() -> Self {
+        EzvizAccounts::default()
+    }
+    pub fn push(&mut self, account: EzvizApi) {
+        self.accounts.push(account);
+    }
+    pub fn accounts(&self) -> &[EzvizApi] {
+        &self.accounts
+    }
+    /// Lists the devices of all accounts, including those shared with them, with each
+    /// device listed once.
+    ///
+    /// A device owned by one of the accounts is listed as that account's, even if it has
+    /// also been shared with another. Otherwise the first account is used. The accounts
+    /// are asked at the same time, and those that fail are reported alongside the devices
+    /// of the others rather than failing the whole listing.
+    pub async fn devices(&self) -> DeviceListing {
+        let results = future::join_all(self.accounts.iter().map(|account| async move {
+            future::try_join(account.devices(), account.shared_devices()).await
+        }))
+        .await;
+        let mut own = vec![];
+        let mut shared = vec![];
+        let mut failed = vec![];
+        for (index, result) in results.into_iter().enumerate() {
+            match result {
+                Ok((devices, shared_devices)) => {
+                    own.push((index, devices));
+                    shared.push((index, shared_devices));
+                }
+                Err(error) => failed.push((index, error)),
+            }
+        }
+        let mut owners = self.owners.lock().await;
+        let previous = std::mem::take(&mut *owners);
+        let mut devices = vec![];
+        for (index, list) in own.into_iter().chain(shared) {
+            for device in list {
+                if !owners.contains_key(&device.serial) {
+                    owners.insert(device.serial.clone(), index);
+                    devices.push(device);
+                }
+            }
+        }
+        // Devices of accounts that could not be asked stay where they were last seen.
+        for (serial, index) in previous {
+            if failed.iter().any(|(failed, _)| *failed == index) {
+                owners.entry(serial).or_insert(index);
+            }
+        }
+        DeviceListing { devices, failed }
+    }
+    /// Returns the account to control a device through, listing the devices again if it
+    /// has not been seen yet.
+    ///
+    /// If the device is not found while some accounts could not be listed, the error of
+    /// the first of those is returned, as the device may belong to it.
+    pub async fn account(&self, serial: &str) -> Result<&EzvizApi, Error> {
+        let known = self.owners.lock().await.get(serial).copied();
+        let index = match known {
+            Some(index) => index,
+            None => {
+                let listing = self.devices().await;
+                let owner = self.owners.lock().await.get(serial).copied();
+                match (owner, listing.failed.into_iter().next()) {
+                    (Some(index), _) => index,
+                    (None, Some((_, error))) => Err(error)?,
+                    (None, None) => Err(Error::UnknownDevice)?,
+                }
+            }
+        };
+        Ok(&self.accounts[index])
+    }
+}
+
+/// Devices of several accounts, as listed by [`EzvizAccounts::devices`].
+#[derive(Debug)]
+pub struct DeviceListing {
+    pub devices: Vec<Device>,
+    /// Accounts whose devices could not be listed, by index into
+    /// [`EzvizAccounts::accounts`], with the error each failed with.
+    pub failed: Vec<(usize, Error)>,
+}
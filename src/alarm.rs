@@ -0,0 +1,176 @@
+use crate::{transport::Request, Error, EzvizApi};
+use chrono::{DateTime, Utc};
+use futures::{stream, Stream};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{collections::VecDeque, time::Duration};
+
+/// Cause of an alarm raised by a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlarmType {
+    Motion,
+    HumanDetection,
+    Sound,
+    Other(i32),
+}
+
+impl From<i32> for AlarmType {
+    fn from(value: i32) -> Self {
+        match value {
+            10000 => AlarmType::Motion,
+            10002 => AlarmType::HumanDetection,
+            10012 => AlarmType::Sound,
+            value => AlarmType::Other(value),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for AlarmType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(i32::deserialize(deserializer)?.into())
+    }
+}
+
+/// An alarm recorded in the cloud, such as a motion detection event.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Alarm {
+    #[serde(rename = "alarmId")]
+    pub id: String,
+    #[serde(rename = "deviceSerial")]
+    pub serial: String,
+    #[serde(rename = "alarmType")]
+    pub kind: AlarmType,
+    #[serde(rename = "alarmStartTime", deserialize_with = "crate::from_millis")]
+    pub time: DateTime<Utc>,
+    /// Picture the device captured when the alarm was raised, if any.
+    #[serde(default, rename = "picUrl")]
+    pub picture_url: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AlarmQuery<'a> {
+    device_serials: &'a str,
+    query_type: i32,
+    stype: i32,
+    limit: usize,
+    start_time: i64,
+    end_time: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct AlarmsResponse {
+    #[serde(default)]
+    alarms: Vec<Alarm>,
+}
+
+/// Maximum number of alarms returned by a single query.
+const ALARM_LIMIT: usize = 50;
+
+struct PollState {
+    since: DateTime<Utc>,
+    /// Alarms already yielded that were raised exactly at `since`.
+    seen: Vec<String>,
+    pending: VecDeque<Alarm>,
+    first: bool,
+}
+
+impl EzvizApi {
+    /// Lists the alarms a device raised between `start` and `end`, oldest first.
+    ///
+    /// The cloud answers a query with at most 50 alarms, newest first, so busier windows
+    /// are fetched page by page, moving the end of the window back to the oldest alarm
+    /// of each full page.
+    pub async fn alarms(
+        &self,
+        serial: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<Alarm>, Error> {
+        let mut alarms = Vec::<Alarm>::new();
+        let mut until = end;
+        loop {
+            let page = self
+                .call::<AlarmsResponse, _>(true, |session| {
+                    Request::get(
+                        self.endpoint
+                            .url(&session.api_domain, "/v3/alarms/v2/advanced"),
+                    )
+                    .query(&AlarmQuery {
+                        device_serials: serial,
+                        query_type: -1,
+                        stype: -1,
+                        limit: ALARM_LIMIT,
+                        start_time: start.timestamp_millis(),
+                        end_time: until.timestamp_millis(),
+                    })
+                })
+                .await?
+                .alarms;
+            let full = page.len() >= ALARM_LIMIT;
+            let oldest = page.iter().map(|alarm| alarm.time).min();
+            let mut added = false;
+            for alarm in page {
+                if !alarms.iter().any(|known| known.id == alarm.id) {
+                    alarms.push(alarm);
+                    added = true;
+                }
+            }
+            // A page that only repeats known alarms means more than a page of them share
+            // the oldest timestamp, and moving the window cannot get past them.
+            match oldest {
+                Some(oldest) if full && added => until = oldest,
+                _ => break,
+            }
+        }
+        alarms.retain(|alarm| alarm.time >= start && alarm.time <= end);
+        alarms.sort_by_key(|alarm| alarm.time);
+        Ok(alarms)
+    }
+
+    /// Polls for alarms raised by a device after `since`, yielding each one once.
+    ///
+    /// The first poll happens immediately, later ones every `interval`. Errors are yielded
+    /// without ending the stream so that a transient failure does not stop monitoring.
+    pub fn alarm_stream<'a>(
+        &'a self,
+        serial: &'a str,
+        since: DateTime<Utc>,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<Alarm, Error>> + 'a {
+        let state = PollState {
+            since,
+            seen: vec![],
+            pending: VecDeque::new(),
+            first: true,
+        };
+        stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(alarm) = state.pending.pop_front() {
+                    return Some((Ok(alarm), state));
+                }
+                if !state.first {
+                    self.client.sleep(interval).await;
+                }
+                state.first = false;
+                let alarms = match self.alarms(serial, state.since, Utc::now()).await {
+                    Ok(alarms) => alarms,
+                    Err(error) => return Some((Err(error), state)),
+                };
+                for alarm in alarms {
+                    if alarm.time == state.since && state.seen.contains(&alarm.id) {
+                        continue;
+                    }
+                    if alarm.time > state.since {
+                        state.since = alarm.time;
+                        state.seen.clear();
+                    }
+                    state.seen.push(alarm.id.clone());
+                    state.pending.push_back(alarm);
+                }
+            }
+        })
+    }
+}
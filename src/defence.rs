@@ -0,0 +1,127 @@
+use crate::{transport::Request, Error, EzvizApi};
+use serde::{de::IgnoredAny, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Account-wide defence mode, which decides whether the devices of the account raise
+/// alarms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefenceMode {
+    Unset,
+    Home,
+    Away,
+    Sleep,
+    Other(i32),
+}
+
+impl DefenceMode {
+    pub fn value(self) -> i32 {
+        match self {
+            DefenceMode::Unset => 0,
+            DefenceMode::Home => 1,
+            DefenceMode::Away => 2,
+            DefenceMode::Sleep => 3,
+            DefenceMode::Other(value) => value,
+        }
+    }
+}
+
+impl From<i32> for DefenceMode {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => DefenceMode::Unset,
+            1 => DefenceMode::Home,
+            2 => DefenceMode::Away,
+            3 => DefenceMode::Sleep,
+            value => DefenceMode::Other(value),
+        }
+    }
+}
+
+impl Serialize for DefenceMode {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for DefenceMode {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(i32::deserialize(deserializer)?.into())
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct GroupQuery {
+    group_id: i32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DefenceModeRequest {
+    group_id: i32,
+    mode: DefenceMode,
+}
+
+#[derive(Debug, Deserialize)]
+struct DefenceModeResponse {
+    mode: DefenceMode,
+}
+
+#[derive(Debug, Serialize)]
+struct DefenceStatusRequest {
+    #[serde(rename = "type")]
+    kind: i32,
+    status: u8,
+    actor: &'static str,
+}
+
+impl EzvizApi {
+    pub async fn defence_mode(&self) -> Result<DefenceMode, Error> {
+        Ok(self
+            .call::<DefenceModeResponse, _>(true, |session| {
+                Request::get(
+                    self.endpoint
+                        .url(&session.api_domain, "/v3/userdevices/v1/group/defenceMode"),
+                )
+                .query(&GroupQuery { group_id: -1 })
+            })
+            .await?
+            .mode)
+    }
+    pub async fn set_defence_mode(&self, mode: DefenceMode) -> Result<(), Error> {
+        self.call::<IgnoredAny, _>(true, |session| {
+            Request::post(self.endpoint.url(
+                &session.api_domain,
+                "/v3/userdevices/v1/group/switchDefenceMode",
+            ))
+            .form(&DefenceModeRequest { group_id: -1, mode })
+        })
+        .await?;
+        Ok(())
+    }
+    /// Returns whether a single device raises alarms, if the device list reports it.
+    pub async fn device_armed(&self, serial: &str) -> Result<Option<bool>, Error> {
+        Ok(self.device(serial).await?.armed)
+    }
+    /// Arms or disarms a single device independently of the account defence mode.
+    pub async fn set_device_armed(&self, serial: &str, armed: bool) -> Result<(), Error> {
+        self.call::<IgnoredAny, _>(true, |session| {
+            Request::put(self.endpoint.url(
+                &session.api_domain,
+                &format!("/v3/devices/{}/1/changeDefenceStatusReq", serial),
+            ))
+            .form(&DefenceStatusRequest {
+                kind: 1,
+                status: armed as u8,
+                actor: "V",
+            })
+        })
+        .await?;
+        Ok(())
+    }
+}
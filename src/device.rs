@@ -0,0 +1,343 @@
+use crate::{transport::Request, Error, EzvizApi};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::{collections::HashMap, net::IpAddr};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Camera {
+    camera_name: String,
+    device_serial: String,
+}
+
+#[derive(Debug, Hash, Eq, PartialEq, Deserialize)]
+#[serde(transparent)]
+struct CameraRef {
+    device_serial: String,
+}
+
+impl<'a> From<&'a Camera> for CameraRef {
+    fn from(cam: &'a Camera) -> Self {
+        CameraRef {
+            device_serial: cam.device_serial.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceInfo {
+    device_serial: String,
+    #[serde(default)]
+    device_type: Option<String>,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    status: Option<i32>,
+    #[serde(default)]
+    defence: Option<i32>,
+}
+
+/// Parses an IP address, treating empty, malformed and unspecified (`0.0.0.0`)
+/// addresses as missing. Offline devices are commonly reported with such values.
+fn ip_or_none<'de, D>(deserializer: D) -> Result<Option<IpAddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?
+        .and_then(|addr| addr.parse::<IpAddr>().ok())
+        .filter(|addr| !addr.is_unspecified()))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Connection {
+    #[serde(default, deserialize_with = "ip_or_none")]
+    local_ip: Option<IpAddr>,
+    #[serde(default, deserialize_with = "ip_or_none")]
+    net_ip: Option<IpAddr>,
+}
+
+/// Wi-Fi connection of a device.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Wifi {
+    #[serde(default, rename = "netName")]
+    pub ssid: Option<String>,
+    /// Signal strength in percent.
+    #[serde(default)]
+    pub signal: Option<i32>,
+}
+
+/// Kind of a device switch, as reported in the `SWITCH` filter of the device list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SwitchType {
+    AlarmTone,
+    StreamAdaptive,
+    StatusLight,
+    IntelligentAnalysis,
+    DefencePlan,
+    Privacy,
+    SoundLocalization,
+    Cruise,
+    InfraredLight,
+    Wifi,
+    Sleep,
+    Sound,
+    MobileTracking,
+    AutoSleep,
+    HumanDetection,
+    Other(i32),
+}
+
+impl SwitchType {
+    pub fn value(self) -> i32 {
+        match self {
+            SwitchType::AlarmTone => 1,
+            SwitchType::StreamAdaptive => 2,
+            SwitchType::StatusLight => 3,
+            SwitchType::IntelligentAnalysis => 4,
+            SwitchType::DefencePlan => 6,
+            SwitchType::Privacy => 7,
+            SwitchType::SoundLocalization => 8,
+            SwitchType::Cruise => 9,
+            SwitchType::InfraredLight => 10,
+            SwitchType::Wifi => 11,
+            SwitchType::Sleep => 21,
+            SwitchType::Sound => 22,
+            SwitchType::MobileTracking => 25,
+            SwitchType::AutoSleep => 32,
+            SwitchType::HumanDetection => 200,
+            SwitchType::Other(value) => value,
+        }
+    }
+}
+
+impl From<i32> for SwitchType {
+    fn from(value: i32) -> Self {
+        match value {
+            1 => SwitchType::AlarmTone,
+            2 => SwitchType::StreamAdaptive,
+            3 => SwitchType::StatusLight,
+            4 => SwitchType::IntelligentAnalysis,
+            6 => SwitchType::DefencePlan,
+            7 => SwitchType::Privacy,
+            8 => SwitchType::SoundLocalization,
+            9 => SwitchType::Cruise,
+            10 => SwitchType::InfraredLight,
+            11 => SwitchType::Wifi,
+            21 => SwitchType::Sleep,
+            22 => SwitchType::Sound,
+            25 => SwitchType::MobileTracking,
+            32 => SwitchType::AutoSleep,
+            200 => SwitchType::HumanDetection,
+            value => SwitchType::Other(value),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SwitchType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(i32::deserialize(deserializer)?.into())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SwitchState {
+    #[serde(rename = "type")]
+    pub kind: SwitchType,
+    pub enable: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Page {
+    #[serde(default)]
+    offset: usize,
+    #[serde(default)]
+    limit: usize,
+    #[serde(default)]
+    has_next: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DevicesResponse {
+    #[serde(default)]
+    page: Page,
+    camera_infos: Vec<Camera>,
+    #[serde(default)]
+    device_infos: Vec<DeviceInfo>,
+    #[serde(default)]
+    connection_infos: HashMap<CameraRef, Connection>,
+    #[serde(default)]
+    wifi_infos: HashMap<CameraRef, Wifi>,
+    #[serde(default)]
+    switch_status_infos: HashMap<CameraRef, Vec<SwitchState>>,
+}
+
+impl DevicesResponse {
+    fn merge(&mut self, other: DevicesResponse) {
+        self.page = other.page;
+        self.camera_infos.extend(other.camera_infos);
+        self.device_infos.extend(other.device_infos);
+        self.connection_infos.extend(other.connection_infos);
+        self.wifi_infos.extend(other.wifi_infos);
+        self.switch_status_infos.extend(other.switch_status_infos);
+    }
+}
+
+/// Whether the cloud currently sees a device as connected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    Online,
+    Offline,
+    /// The device list did not include a status for the device.
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub name: String,
+    pub serial: String,
+    pub model: Option<String>,
+    pub firmware_version: Option<String>,
+    pub connectivity: Connectivity,
+    /// Whether the device raises alarms, if the device list reported it.
+    pub armed: Option<bool>,
+    /// Address of the device on its local network, if the cloud knows it.
+    pub addr: Option<IpAddr>,
+    /// Public address the device connects to the cloud from, if the cloud knows it.
+    pub net_addr: Option<IpAddr>,
+    pub wifi: Option<Wifi>,
+    pub switches: Vec<SwitchState>,
+    /// Whether another account owns the device and shared it with this one.
+    pub shared: bool,
+}
+
+impl Device {
+    /// Returns the local address of the device, falling back to its public address for
+    /// access from outside its network.
+    pub fn reachable_addr(&self) -> Option<IpAddr> {
+        self.addr.or(self.net_addr)
+    }
+    /// Returns whether the given switch is enabled, if the device reported it.
+    pub fn switch(&self, kind: SwitchType) -> Option<bool> {
+        self.switches
+            .iter()
+            .find(|switch| switch.kind == kind)
+            .map(|switch| switch.enable)
+    }
+}
+
+impl From<DevicesResponse> for Vec<Device> {
+    fn from(value: DevicesResponse) -> Self {
+        let DevicesResponse {
+            page: _,
+            camera_infos,
+            device_infos,
+            connection_infos,
+            mut wifi_infos,
+            mut switch_status_infos,
+        } = value;
+        let mut infos = device_infos
+            .into_iter()
+            .map(|info| (info.device_serial.clone(), info))
+            .collect::<HashMap<_, _>>();
+        camera_infos
+            .iter()
+            .map(|item| {
+                let key = item.into();
+                let connection = connection_infos.get(&key);
+                let info = infos.remove(&item.device_serial);
+                Device {
+                    name: item.camera_name.clone(),
+                    serial: item.device_serial.clone(),
+                    model: info.as_ref().and_then(|info| info.device_type.clone()),
+                    firmware_version: info.as_ref().and_then(|info| info.version.clone()),
+                    connectivity: match info.as_ref().and_then(|info| info.status) {
+                        Some(1) => Connectivity::Online,
+                        Some(_) => Connectivity::Offline,
+                        None => Connectivity::Unknown,
+                    },
+                    armed: info
+                        .as_ref()
+                        .and_then(|info| info.defence)
+                        .map(|defence| defence != 0),
+                    addr: connection.and_then(|connection| connection.local_ip),
+                    net_addr: connection.and_then(|connection| connection.net_ip),
+                    wifi: wifi_infos.remove(&key),
+                    switches: switch_status_infos.remove(&key).unwrap_or_default(),
+                    shared: false,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Number of devices requested per page of the device list.
+const PAGE_SIZE: usize = 30;
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PageQuery {
+    filter: &'static str,
+    group_id: i32,
+    limit: usize,
+    offset: usize,
+}
+
+impl EzvizApi {
+    async fn device_page(&self, path: &str, offset: usize) -> Result<DevicesResponse, Error> {
+        self.call(true, |session| {
+            Request::get(self.endpoint.url(&session.api_domain, path))
+            .query(&PageQuery {
+                filter: "CLOUD,TIME_PLAN,CONNECTION,SWITCH,STATUS,WIFI,STATUS_EXT,NODISTURB,P2P,TTS,KMS,HIDDNS",
+                group_id: -1,
+                limit: PAGE_SIZE,
+                offset,
+            })
+        })
+        .await
+    }
+    /// Fetches every page of a device list.
+    async fn device_list(&self, path: &str) -> Result<Vec<Device>, Error> {
+        let mut response = self.device_page(path, 0).await?;
+        // Pages count devices, not cameras: an NVR lists a camera for each of its
+        // channels. The next page starts where the server says this one ended.
+        while response.page.has_next && response.page.limit > 0 {
+            let offset = response.page.offset + response.page.limit;
+            let page = self.device_page(path, offset).await?;
+            if page.camera_infos.is_empty() {
+                break;
+            }
+            response.merge(page);
+        }
+        Ok(response.into())
+    }
+    /// Lists all devices of the account, fetching as many pages as needed.
+    pub async fn devices(&self) -> Result<Vec<Device>, Error> {
+        self.device_list("/v3/userdevices/v1/devices/pagelist")
+            .await
+    }
+    /// Lists the devices other accounts shared with this one.
+    pub async fn shared_devices(&self) -> Result<Vec<Device>, Error> {
+        let mut devices = self
+            .device_list("/v3/userdevices/v1/share/devices/pagelist")
+            .await?;
+        for device in &mut devices {
+            device.shared = true;
+        }
+        Ok(devices)
+    }
+    /// Looks up a single device of the account by its serial.
+    pub async fn device(&self, serial: &str) -> Result<Device, Error> {
+        self.devices()
+            .await?
+            .into_iter()
+            .find(|device| device.serial == serial)
+            .ok_or(Error::UnknownDevice)
+    }
+}
@@ -0,0 +1,141 @@
+//! Discovery of cameras on the local network with SADP, the multicast probe Hikvision and
+//! EZVIZ devices answer, without logging in to the cloud.
+
+use crate::{xml, Error};
+use futures::future::{self, Either};
+use smol::{net::UdpSocket, Timer};
+use std::{
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    time::{Duration, Instant},
+};
+
+const SADP_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+/// UDP port devices listen for probes on.
+pub const SADP_PORT: u16 = 37020;
+
+/// A camera that answered a discovery probe.
+#[derive(Debug, Clone)]
+pub struct LanDevice {
+    /// The serial the device is known by in the cloud, as in
+    /// [`Device::serial`](crate::Device::serial).
+    pub serial: String,
+    /// The full serial number, which is the model followed by the date of manufacture
+    /// and the serial.
+    pub serial_number: String,
+    pub addr: IpAddr,
+    pub model: Option<String>,
+    pub firmware_version: Option<String>,
+    pub mac: Option<String>,
+    /// Whether the device has been set up. Devices that have not are not streaming yet.
+    pub activated: Option<bool>,
+}
+
+impl LanDevice {
+    fn from_probe_match(xml: &str) -> Option<Self> {
+        if xml::text(xml, "Types")? != "inquiry" || xml::element(xml, "ProbeMatch").is_none() {
+            return None;
+        }
+        let serial_number = xml::text(xml, "DeviceSN")?.to_owned();
+        let serial = serial_number
+            .get(serial_number.len().saturating_sub(9)..)
+            .unwrap_or(&serial_number)
+            .to_owned();
+        Some(LanDevice {
+            serial,
+            addr: xml::text(xml, "IPv4Address")?.parse().ok()?,
+            model: xml::text(xml, "DeviceDescription").map(str::to_owned),
+            firmware_version: xml::text(xml, "SoftwareVersion").map(str::to_owned),
+            mac: xml::text(xml, "MAC").map(str::to_owned),
+            activated: xml::text(xml, "Activated").map(|activated| activated == "true"),
+            serial_number,
+        })
+    }
+}
+
+fn probe() -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?><Probe><Uuid>{}</Uuid><Types>inquiry</Types></Probe>"#,
+        uuid::Uuid::new_v4().to_string().to_uppercase()
+    )
+}
+
+/// Collects the answers to a probe sent from `socket` until `timeout` has passed.
+///
+/// Each answer is handed to `parse` along with its sender, and of the devices it returns
+/// only the first with each `key` is kept. Shared by SADP and ONVIF discovery.
+pub(crate) async fn collect<T, K: PartialEq>(
+    socket: &UdpSocket,
+    timeout: Duration,
+    parse: impl Fn(&str, SocketAddr) -> Option<T>,
+    key: impl Fn(&T) -> K,
+) -> Result<Vec<T>, Error> {
+    let deadline = Instant::now() + timeout;
+    let mut devices = Vec::<T>::new();
+    let mut buf = vec![0; 65536];
+    loop {
+        let (len, from) =
+            match future::select(Box::pin(socket.recv_from(&mut buf)), Timer::at(deadline)).await {
+                Either::Left((received, _)) => received?,
+                Either::Right(_) => break,
+            };
+        if let Some(device) = std::str::from_utf8(&buf[..len])
+            .ok()
+            .and_then(|answer| parse(answer, from))
+        {
+            if !devices.iter().any(|known| key(known) == key(&device)) {
+                devices.push(device);
+            }
+        }
+    }
+    Ok(devices)
+}
+
+/// Collects SADP answers, keeping one per serial.
+async fn collect_sadp(socket: &UdpSocket, timeout: Duration) -> Result<Vec<LanDevice>, Error> {
+    let mut devices = collect(
+        socket,
+        timeout,
+        |answer, _| LanDevice::from_probe_match(answer),
+        |device| device.serial.clone(),
+    )
+    .await?;
+    devices.sort_by(|a, b| a.serial.cmp(&b.serial));
+    Ok(devices)
+}
+
+/// Binds the SADP port and joins the multicast group, which devices answer probes to.
+async fn sadp_socket() -> Result<UdpSocket, Error> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, SADP_PORT)).await?;
+    socket.join_multicast_v4(SADP_GROUP, Ipv4Addr::UNSPECIFIED)?;
+    socket.set_multicast_loop_v4(false)?;
+    Ok(socket)
+}
+
+/// Finds the cameras on the local network, listening for answers for `timeout`.
+///
+/// Devices answer to the SADP multicast group, so this binds the SADP port and fails if
+/// another program, such as the SADP tool, already has it.
+///
+/// This uses SADP, which EZVIZ and Hikvision devices answer. See
+/// [`onvif::discover`](crate::onvif::discover) for cameras of other brands.
+pub async fn discover_sadp(timeout: Duration) -> Result<Vec<LanDevice>, Error> {
+    let socket = sadp_socket().await?;
+    socket
+        .send_to(probe().as_bytes(), (SADP_GROUP, SADP_PORT))
+        .await?;
+    collect_sadp(&socket, timeout).await
+}
+
+/// Probes a single IPv4 address, usually at [`SADP_PORT`], e.g. a camera on a network that
+/// multicast probes do not reach, and returns what answered within `timeout`.
+///
+/// Devices answer a probe sent to them either directly or to the multicast group, so
+/// this listens on the group too and, like [`discover_sadp`], needs the SADP port.
+pub async fn discover_sadp_at(
+    target: SocketAddr,
+    timeout: Duration,
+) -> Result<Vec<LanDevice>, Error> {
+    let socket = sadp_socket().await?;
+    socket.send_to(probe().as_bytes(), target).await?;
+    collect_sadp(&socket, timeout).await
+}
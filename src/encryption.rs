@@ -0,0 +1,78 @@
+use crate::{transport::Request, Error, EzvizApi, EzvizFeatureCode, LegacyRejection, MetaEnvelope};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptKeyRequest<'a> {
+    serial: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checkcode: Option<&'a str>,
+    client_no: &'static str,
+    client_type: u8,
+    net_type: &'static str,
+    feature_code: EzvizFeatureCode,
+    session_id: &'a str,
+}
+
+/// Response of the older `/api` endpoints, which report a `resultCode` instead of `meta`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptKeyResponse {
+    result_code: String,
+    #[serde(default)]
+    result_des: String,
+    #[serde(default)]
+    encryptkey: Option<String>,
+}
+
+impl EzvizApi {
+    /// Fetches the verification code of a device, which is printed on its label and
+    /// needed for [`camera_stream`](crate::camera_stream) and to decrypt its pictures.
+    ///
+    /// Fails with [`Error::VerificationRequired`] if the account has to confirm access
+    /// with an SMS or email verification code, which is then passed as `sms_code`.
+    pub async fn verification_code(
+        &self,
+        serial: &str,
+        sms_code: Option<&str>,
+    ) -> Result<String, Error> {
+        self.call_with(
+            true,
+            |session| {
+                Request::post(
+                    self.endpoint
+                        .url(&session.api_domain, "/api/device/query/encryptkey"),
+                )
+                .form(&EncryptKeyRequest {
+                    serial,
+                    checkcode: sms_code,
+                    client_no: "web_site",
+                    client_type: 3,
+                    net_type: "WIFI",
+                    feature_code: EzvizFeatureCode,
+                    session_id: &session.session_id,
+                })
+            },
+            |response| {
+                // Sessions are rejected by the gateway in front of the endpoint, with `meta`.
+                if let Ok(envelope) = serde_json::from_slice::<MetaEnvelope>(&response.body) {
+                    if envelope.meta.is_session_expired() {
+                        return Ok(None);
+                    }
+                }
+                let response = serde_json::from_slice::<EncryptKeyResponse>(&response.body)?;
+                let rejection = LegacyRejection {
+                    code: response.result_code,
+                    message: response.result_des,
+                };
+                match rejection.code.as_str() {
+                    "0" => Ok(Some(response.encryptkey.ok_or(Error::Unsupported)?)),
+                    "20002" if sms_code.is_some() => Err(Error::InvalidVerificationCode),
+                    "20002" => Err(Error::VerificationRequired),
+                    _ => Err(Error::LegacyApi(rejection)),
+                }
+            },
+        )
+        .await
+    }
+}
@@ -0,0 +1,573 @@
+//! Local access to cameras that speak ONVIF, which includes many EZVIZ models as well as
+//! most cameras of other brands, without going through the cloud.
+//!
+//! Devices are found with [`discover`] and controlled through an [`OnvifCamera`]. Their
+//! video is decoded by handing a [stream URI](OnvifCamera::stream_uri) to
+//! [`rtsp_stream`](crate::rtsp_stream), which [`OnvifCamera::stream`] does with the
+//! credentials filled in.
+
+use crate::{
+    discovery, rtsp_stream,
+    transport::{Request, SurfTransport, Transport},
+    xml, Error,
+};
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use futures::{lock::Mutex, stream, Stream};
+use smol::net::UdpSocket;
+use std::{
+    collections::VecDeque,
+    fmt,
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+    time::Duration,
+};
+
+const DISCOVERY_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 255, 250);
+/// UDP port devices listen for WS-Discovery probes on.
+pub const DISCOVERY_PORT: u16 = 3702;
+
+const DEVICE_NS: &str = "http://www.onvif.org/ver10/device/wsdl";
+const MEDIA_NS: &str = "http://www.onvif.org/ver10/media/wsdl";
+const EVENTS_NS: &str = "http://www.onvif.org/ver10/events/wsdl";
+
+/// How long a pull point waits for events before answering with none.
+const PULL_TIMEOUT: &str = "PT10S";
+const PULL_LIMIT: u32 = 32;
+
+/// A device that answered a discovery probe.
+#[derive(Debug, Clone)]
+pub struct OnvifDevice {
+    /// Identifies the device across address changes, usually a `urn:uuid:` URN.
+    pub id: String,
+    /// Address the answer came from.
+    pub addr: IpAddr,
+    /// URLs of the device service, one of which is passed to [`OnvifCamera::new`].
+    pub urls: Vec<String>,
+    /// Name the device has been given, from its scopes.
+    pub name: Option<String>,
+    /// Model of the device, from its scopes.
+    pub hardware: Option<String>,
+    pub scopes: Vec<String>,
+}
+
+impl OnvifDevice {
+    fn from_probe_match(addr: IpAddr, xml: &str) -> Option<Self> {
+        let probe_match = xml::element(xml, "ProbeMatch")?.content;
+        let list = |name| {
+            xml::text(probe_match, name)
+                .map(|list| list.split_whitespace().map(xml::unescape).collect())
+                .unwrap_or_default()
+        };
+        let scopes: Vec<String> = list("Scopes");
+        let scope = |prefix: &str| {
+            scopes
+                .iter()
+                .find_map(|scope| scope.strip_prefix(prefix))
+                .map(percent_decode)
+        };
+        Some(OnvifDevice {
+            id: xml::unescape(xml::text(probe_match, "Address")?),
+            addr,
+            urls: list("XAddrs"),
+            name: scope("onvif://www.onvif.org/name/"),
+            hardware: scope("onvif://www.onvif.org/hardware/"),
+            scopes,
+        })
+    }
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match (bytes[i], text.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) => u8::from_str_radix(hex, 16).ok(),
+            _ => None,
+        };
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn probe() -> String {
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope" "#,
+            r#"xmlns:a="http://schemas.xmlsoap.org/ws/2004/08/addressing" "#,
+            r#"xmlns:d="http://schemas.xmlsoap.org/ws/2005/04/discovery" "#,
+            r#"xmlns:dn="http://www.onvif.org/ver10/network/wsdl">"#,
+            r#"<s:Header>"#,
+            r#"<a:Action s:mustUnderstand="1">http://schemas.xmlsoap.org/ws/2005/04/discovery/Probe</a:Action>"#,
+            r#"<a:MessageID>uuid:{}</a:MessageID>"#,
+            r#"<a:ReplyTo><a:Address>http://schemas.xmlsoap.org/ws/2004/08/addressing/role/anonymous</a:Address></a:ReplyTo>"#,
+            r#"<a:To s:mustUnderstand="1">urn:schemas-xmlsoap-org:ws:2005:04:discovery</a:To>"#,
+            r#"</s:Header>"#,
+            r#"<s:Body><d:Probe><d:Types>dn:NetworkVideoTransmitter</d:Types></d:Probe></s:Body>"#,
+            r#"</s:Envelope>"#
+        ),
+        uuid::Uuid::new_v4()
+    )
+}
+
+/// Finds the ONVIF cameras on the local network, listening for answers for `timeout`.
+///
+/// EZVIZ cameras with ONVIF turned off are only found by
+/// [`discover_sadp`](crate::discover_sadp).
+pub async fn discover(timeout: Duration) -> Result<Vec<OnvifDevice>, Error> {
+    discover_at((DISCOVERY_GROUP, DISCOVERY_PORT).into(), timeout).await
+}
+
+/// Probes a single address, usually the multicast group or a camera at
+/// [`DISCOVERY_PORT`], and returns what answered within `timeout`.
+pub async fn discover_at(
+    target: std::net::SocketAddr,
+    timeout: Duration,
+) -> Result<Vec<OnvifDevice>, Error> {
+    // Answers are sent straight back to the probe's address, so unlike SADP no
+    // well-known port has to be bound.
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.send_to(probe().as_bytes(), target).await?;
+    discovery::collect(
+        &socket,
+        timeout,
+        |answer, from| OnvifDevice::from_probe_match(from.ip(), answer),
+        |device| device.id.clone(),
+    )
+    .await
+}
+
+/// What a device reports about itself.
+#[derive(Debug, Clone)]
+pub struct DeviceInformation {
+    pub manufacturer: String,
+    pub model: String,
+    pub firmware_version: String,
+    pub serial_number: String,
+    pub hardware_id: String,
+}
+
+/// A set of encoder settings a camera streams with, such as its main and sub stream.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// Identifies the profile in [`OnvifCamera::stream_uri`] and the like.
+    pub token: String,
+    pub name: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// A change of the motion state reported by a camera.
+#[derive(Debug, Clone)]
+pub struct MotionEvent {
+    pub time: DateTime<Utc>,
+    /// Topic the event was published under, e.g. `tns1:RuleEngine/CellMotionDetector/Motion`.
+    pub topic: String,
+    /// Whether motion is being detected from now on.
+    pub active: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Services {
+    media: String,
+    events: Option<String>,
+}
+
+/// A camera accessed over ONVIF.
+///
+/// Requests are authenticated with a WS-Security password digest, which includes the
+/// time. Cameras reject it when their clock is off, so the difference to the camera's
+/// clock is measured first and accounted for.
+pub struct OnvifCamera {
+    transport: Arc<dyn Transport>,
+    url: String,
+    username: String,
+    password: String,
+    clock_offset: Mutex<Option<chrono::Duration>>,
+    services: Mutex<Option<Services>>,
+}
+
+impl fmt::Debug for OnvifCamera {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnvifCamera")
+            .field("transport", &self.transport)
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish_non_exhaustive()
+    }
+}
+
+impl OnvifCamera {
+    /// Creates a camera from the URL of its device service, usually
+    /// `http://<address>/onvif/device_service`. EZVIZ cameras take the user `admin` and
+    /// their verification code.
+    pub fn new<T: Into<String>, U: Into<String>, V: Into<String>>(
+        url: T,
+        username: U,
+        password: V,
+    ) -> Self {
+        OnvifCamera {
+            transport: Arc::new(SurfTransport::new()),
+            url: url.into(),
+            username: username.into(),
+            password: password.into(),
+            clock_offset: Mutex::new(None),
+            services: Mutex::new(None),
+        }
+    }
+    /// Sends requests through `transport` instead of the default [`SurfTransport`].
+    pub fn transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    async fn send(
+        &self,
+        url: &str,
+        action: &str,
+        header: &str,
+        body: &str,
+    ) -> Result<String, Error> {
+        let envelope = format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<s:Envelope xmlns:s="http://www.w3.org/2003/05/soap-envelope" "#,
+                r#"xmlns:a="http://www.w3.org/2005/08/addressing" "#,
+                r#"xmlns:tt="http://www.onvif.org/ver10/schema">"#,
+                r#"<s:Header><a:Action>{}</a:Action><a:To>{}</a:To>{}</s:Header>"#,
+                r#"<s:Body>{}</s:Body></s:Envelope>"#
+            ),
+            xml::escape(action),
+            xml::escape(url),
+            header,
+            body
+        );
+        let request = Request::post(url).body(
+            &format!(
+                r#"application/soap+xml; charset=utf-8; action="{}""#,
+                action
+            ),
+            envelope,
+        );
+        let response = self.transport.send(request).await?;
+        let body = String::from_utf8_lossy(&response.body).into_owned();
+        if response.status == 401 {
+            Err(Error::InvalidCredentials)?;
+        }
+        if let Some(fault) = xml::element(&body, "Fault") {
+            if xml::elements(fault.content, "Value")
+                .iter()
+                .filter_map(|value| value.text())
+                .any(|value| value.ends_with("NotAuthorized"))
+            {
+                Err(Error::InvalidCredentials)?;
+            }
+            let reason = xml::text(fault.content, "Text").unwrap_or("unknown fault");
+            Err(Error::Camera(xml::unescape(reason)))?;
+        }
+        if !(200..300).contains(&response.status) {
+            Err(Error::Camera(format!("HTTP status {}", response.status)))?;
+        }
+        Ok(body)
+    }
+    /// Returns how far the camera's clock is ahead, measuring it on first use.
+    async fn clock_offset(&self) -> chrono::Duration {
+        let mut offset = self.clock_offset.lock().await;
+        if let Some(offset) = *offset {
+            return offset;
+        }
+        // Cameras answer this without authentication, so that clients can do just this.
+        let measured = self
+            .send(
+                &self.url,
+                &format!("{}/GetSystemDateAndTime", DEVICE_NS),
+                "",
+                &format!(r#"<GetSystemDateAndTime xmlns="{}"/>"#, DEVICE_NS),
+            )
+            .await
+            .ok()
+            .and_then(|body| {
+                let utc = xml::element(&body, "UTCDateTime")?.content;
+                let field = |name| xml::text(utc, name)?.parse::<u32>().ok();
+                let time = Utc
+                    .ymd_opt(field("Year")? as i32, field("Month")?, field("Day")?)
+                    .single()?
+                    .and_hms_opt(field("Hour")?, field("Minute")?, field("Second")?)?;
+                Some(time - Utc::now())
+            })
+            .unwrap_or_else(chrono::Duration::zero);
+        *offset = Some(measured);
+        measured
+    }
+    async fn call(&self, url: &str, action: &str, body: &str) -> Result<String, Error> {
+        let created =
+            (Utc::now() + self.clock_offset().await).to_rfc3339_opts(SecondsFormat::Secs, true);
+        let nonce: Vec<u8> = std::iter::repeat_with(|| fastrand::u8(..))
+            .take(16)
+            .collect();
+        let mut digest = sha1::Sha1::new();
+        digest.update(&nonce);
+        digest.update(created.as_bytes());
+        digest.update(self.password.as_bytes());
+        let header = format!(
+            concat!(
+                r#"<Security s:mustUnderstand="1" xmlns="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-secext-1.0.xsd">"#,
+                r#"<UsernameToken><Username>{}</Username>"#,
+                r#"<Password Type="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-username-token-profile-1.0#PasswordDigest">{}</Password>"#,
+                r#"<Nonce EncodingType="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-soap-message-security-1.0#Base64Binary">{}</Nonce>"#,
+                r#"<Created xmlns="http://docs.oasis-open.org/wss/2004/01/oasis-200401-wss-wssecurity-utility-1.0.xsd">{}</Created>"#,
+                r#"</UsernameToken></Security>"#
+            ),
+            xml::escape(&self.username),
+            base64::encode(digest.digest().bytes()),
+            base64::encode(&nonce),
+            created
+        );
+        self.send(url, action, &header, body).await
+    }
+    /// Returns the URLs of the media and event services, asking the camera on first use.
+    async fn services(&self) -> Result<Services, Error> {
+        let mut services = self.services.lock().await;
+        if let Some(services) = &*services {
+            return Ok(services.clone());
+        }
+        let body = self
+            .call(
+                &self.url,
+                &format!("{}/GetCapabilities", DEVICE_NS),
+                &format!(
+                    r#"<GetCapabilities xmlns="{}"><Category>All</Category></GetCapabilities>"#,
+                    DEVICE_NS
+                ),
+            )
+            .await?;
+        let address = |service| {
+            xml::element(&body, service)
+                .and_then(|service| xml::text(service.content, "XAddr"))
+                .map(xml::unescape)
+        };
+        let found = Services {
+            media: address("Media").unwrap_or_else(|| self.url.clone()),
+            events: address("Events"),
+        };
+        *services = Some(found.clone());
+        Ok(found)
+    }
+    pub async fn device_information(&self) -> Result<DeviceInformation, Error> {
+        let body = self
+            .call(
+                &self.url,
+                &format!("{}/GetDeviceInformation", DEVICE_NS),
+                &format!(r#"<GetDeviceInformation xmlns="{}"/>"#, DEVICE_NS),
+            )
+            .await?;
+        let field = |name| {
+            xml::text(&body, name)
+                .map(xml::unescape)
+                .unwrap_or_default()
+        };
+        Ok(DeviceInformation {
+            manufacturer: field("Manufacturer"),
+            model: field("Model"),
+            firmware_version: field("FirmwareVersion"),
+            serial_number: field("SerialNumber"),
+            hardware_id: field("HardwareId"),
+        })
+    }
+    /// Lists the media profiles of the camera, usually starting with its main stream.
+    pub async fn profiles(&self) -> Result<Vec<Profile>, Error> {
+        let media = self.services().await?.media;
+        let body = self
+            .call(
+                &media,
+                &format!("{}/GetProfiles", MEDIA_NS),
+                &format!(r#"<GetProfiles xmlns="{}"/>"#, MEDIA_NS),
+            )
+            .await?;
+        Ok(xml::elements(&body, "Profiles")
+            .into_iter()
+            .filter_map(|profile| {
+                let resolution = xml::element(profile.content, "Resolution");
+                let dimension = |name| {
+                    resolution
+                        .and_then(|resolution| xml::text(resolution.content, name))
+                        .and_then(|value| value.parse().ok())
+                };
+                Some(Profile {
+                    token: xml::unescape(profile.attribute("token")?),
+                    name: xml::text(profile.content, "Name")
+                        .map(xml::unescape)
+                        .unwrap_or_default(),
+                    width: dimension("Width"),
+                    height: dimension("Height"),
+                })
+            })
+            .collect())
+    }
+    async fn media_uri(
+        &self,
+        operation: &str,
+        setup: &str,
+        profile: &str,
+    ) -> Result<String, Error> {
+        let media = self.services().await?.media;
+        let body = self
+            .call(
+                &media,
+                &format!("{}/{}", MEDIA_NS, operation),
+                &format!(
+                    r#"<{0} xmlns="{1}">{2}<ProfileToken>{3}</ProfileToken></{0}>"#,
+                    operation,
+                    MEDIA_NS,
+                    setup,
+                    xml::escape(profile)
+                ),
+            )
+            .await?;
+        xml::element(&body, "MediaUri")
+            .and_then(|uri| xml::text(uri.content, "Uri"))
+            .map(xml::unescape)
+            .ok_or(Error::Unsupported)
+    }
+    /// Returns the RTSP URI of a profile's stream, without credentials.
+    pub async fn stream_uri(&self, profile: &str) -> Result<String, Error> {
+        self.media_uri(
+            "GetStreamUri",
+            concat!(
+                r#"<StreamSetup><tt:Stream>RTP-Unicast</tt:Stream>"#,
+                r#"<tt:Transport><tt:Protocol>RTSP</tt:Protocol></tt:Transport></StreamSetup>"#
+            ),
+            profile,
+        )
+        .await
+    }
+    /// Returns the HTTP URI a JPEG snapshot of a profile's picture can be fetched from,
+    /// usually with HTTP digest authentication.
+    pub async fn snapshot_uri(&self, profile: &str) -> Result<String, Error> {
+        self.media_uri("GetSnapshotUri", "", profile).await
+    }
+    /// Decodes the stream of a profile, like [`camera_stream`](crate::camera_stream) does
+    /// for EZVIZ cameras.
+    pub async fn stream(
+        &self,
+        profile: &str,
+    ) -> Result<impl Stream<Item = image::ImageBuffer<image::Rgb<u8>, Vec<u8>>>, Error> {
+        let uri = self.stream_uri(profile).await?;
+        let mut uri = surf::Url::parse(&uri)
+            .map_err(|error| Error::Camera(format!("invalid stream URI {}: {}", uri, error)))?;
+        // Setting credentials only fails for URIs without a host, which cannot be streamed.
+        uri.set_username(&self.username)
+            .and_then(|_| uri.set_password(Some(&self.password)))
+            .map_err(|_| Error::Camera(format!("invalid stream URI {}", uri)))?;
+        Ok(rtsp_stream(uri.to_string()))
+    }
+    async fn subscribe(&self) -> Result<String, Error> {
+        let events = self.services().await?.events.ok_or(Error::Unsupported)?;
+        let body = self
+            .call(
+                &events,
+                &format!(
+                    "{}/EventPortType/CreatePullPointSubscriptionRequest",
+                    EVENTS_NS
+                ),
+                &format!(r#"<CreatePullPointSubscription xmlns="{}"/>"#, EVENTS_NS),
+            )
+            .await?;
+        xml::element(&body, "SubscriptionReference")
+            .and_then(|reference| xml::text(reference.content, "Address"))
+            .map(xml::unescape)
+            .ok_or_else(|| Error::Camera("no subscription address".to_owned()))
+    }
+    async fn pull(&self, subscription: &str) -> Result<Vec<MotionEvent>, Error> {
+        let body = self
+            .call(
+                subscription,
+                &format!(
+                    "{}/PullPointSubscription/PullMessagesRequest",
+                    EVENTS_NS
+                ),
+                &format!(
+                    r#"<PullMessages xmlns="{}"><Timeout>{}</Timeout><MessageLimit>{}</MessageLimit></PullMessages>"#,
+                    EVENTS_NS, PULL_TIMEOUT, PULL_LIMIT
+                ),
+            )
+            .await?;
+        Ok(xml::elements(&body, "NotificationMessage")
+            .into_iter()
+            .filter_map(|notification| motion_event(notification.content))
+            .collect())
+    }
+    /// Subscribes to the motion events of the camera.
+    ///
+    /// The current state is usually reported first. The subscription is renewed if the
+    /// camera drops it, and the stream ends after the first error it cannot recover from.
+    pub fn motion_events(&self) -> impl Stream<Item = Result<MotionEvent, Error>> + '_ {
+        let state = (None::<String>, VecDeque::new(), false);
+        stream::unfold(
+            state,
+            move |(mut subscription, mut pending, done)| async move {
+                loop {
+                    if let Some(event) = pending.pop_front() {
+                        return Some((Ok(event), (subscription, pending, done)));
+                    }
+                    if done {
+                        return None;
+                    }
+                    let (address, fresh) = match subscription.take() {
+                        Some(address) => (address, false),
+                        None => match self.subscribe().await {
+                            Ok(address) => (address, true),
+                            Err(error) => return Some((Err(error), (None, pending, true))),
+                        },
+                    };
+                    match self.pull(&address).await {
+                        Ok(events) => {
+                            pending.extend(events);
+                            subscription = Some(address);
+                        }
+                        // The subscription may have expired, so the next pull uses a new one.
+                        Err(Error::Camera(_)) if !fresh => {}
+                        Err(error) => return Some((Err(error), (None, pending, true))),
+                    }
+                }
+            },
+        )
+    }
+}
+
+/// Reads the motion state from a notification, ignoring other kinds of events.
+fn motion_event(notification: &str) -> Option<MotionEvent> {
+    let topic = xml::text(notification, "Topic")?.to_owned();
+    let data = xml::element(notification, "Data")?.content;
+    let item = xml::elements(data, "SimpleItem").into_iter().find(|item| {
+        match item.attribute("Name") {
+            Some("IsMotion") => true,
+            Some("State") => topic.contains("Motion"),
+            _ => false,
+        }
+    })?;
+    // The time is on the inner of the two nested `Message` elements.
+    let message = xml::element(notification, "Message")?;
+    let time = message
+        .attribute("UtcTime")
+        .or_else(|| xml::element(message.content, "Message")?.attribute("UtcTime"))
+        .and_then(|time| DateTime::parse_from_rfc3339(time).ok())
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(Utc::now);
+    Some(MotionEvent {
+        time,
+        topic,
+        active: item.attribute("Value")? == "true",
+    })
+}
@@ -0,0 +1,146 @@
+use crate::{transport::Request, Error, EzvizApi, LegacyRejection};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+
+/// Direction to pan or tilt a camera in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum PtzDirection {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+enum PtzAction {
+    Start,
+    Stop,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PtzControl<'a> {
+    command: PtzDirection,
+    action: PtzAction,
+    channel_no: u32,
+    speed: u8,
+    uuid: String,
+    serial: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PresetRequest<'a> {
+    device_serial: &'a str,
+    channel_no: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PresetIndex {
+    index: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct PresetResponse {
+    data: PresetIndex,
+}
+
+/// The preset endpoints report devices without PTZ support with codes of their own.
+fn preset_error(rejection: LegacyRejection) -> Error {
+    match rejection.code.as_str() {
+        // PTZ is not supported, or the command is not.
+        "60000" | "60020" => Error::Unsupported,
+        _ => Error::LegacyApi(rejection),
+    }
+}
+
+/// Pan/tilt control of cameras that have a motorized mount.
+///
+/// Devices without PTZ support make these calls fail with [`Error::Unsupported`].
+impl EzvizApi {
+    async fn ptz_control(
+        &self,
+        serial: &str,
+        direction: PtzDirection,
+        action: PtzAction,
+        speed: u8,
+    ) -> Result<(), Error> {
+        self.call::<IgnoredAny, _>(true, |session| {
+            Request::put(self.endpoint.url(
+                &session.api_domain,
+                &format!("/v3/devices/{}/ptzControl", serial),
+            ))
+            .form(&PtzControl {
+                command: direction,
+                action,
+                channel_no: 1,
+                speed,
+                uuid: uuid::Uuid::new_v4().to_string(),
+                serial,
+            })
+        })
+        .await?;
+        Ok(())
+    }
+    /// Starts moving the camera in `direction` until [`EzvizApi::ptz_stop`] is called or
+    /// the mount reaches its limit. `speed` ranges from 1 (slowest) to 7.
+    pub async fn ptz_start(
+        &self,
+        serial: &str,
+        direction: PtzDirection,
+        speed: u8,
+    ) -> Result<(), Error> {
+        self.ptz_control(serial, direction, PtzAction::Start, speed)
+            .await
+    }
+    /// Stops a movement started with [`EzvizApi::ptz_start`].
+    pub async fn ptz_stop(&self, serial: &str, direction: PtzDirection) -> Result<(), Error> {
+        self.ptz_control(serial, direction, PtzAction::Stop, 0)
+            .await
+    }
+    /// Saves the current position of the camera as a preset and returns its index.
+    pub async fn ptz_save_preset(&self, serial: &str) -> Result<u32, Error> {
+        Ok(self
+            .call_legacy_with::<PresetResponse, _, _>(
+                false,
+                |session| {
+                    Request::post(
+                        self.endpoint
+                            .url(&session.api_domain, "/api/device/preset/add"),
+                    )
+                    .form(&PresetRequest {
+                        device_serial: serial,
+                        channel_no: 1,
+                        index: None,
+                    })
+                },
+                preset_error,
+            )
+            .await?
+            .data
+            .index)
+    }
+    /// Moves the camera to a preset previously saved with [`EzvizApi::ptz_save_preset`].
+    pub async fn ptz_goto_preset(&self, serial: &str, index: u32) -> Result<(), Error> {
+        self.call_legacy_with::<IgnoredAny, _, _>(
+            true,
+            |session| {
+                Request::post(
+                    self.endpoint
+                        .url(&session.api_domain, "/api/device/preset/move"),
+                )
+                .form(&PresetRequest {
+                    device_serial: serial,
+                    channel_no: 1,
+                    index: Some(index),
+                })
+            },
+            preset_error,
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,158 @@
+use crate::{
+    transport::{Request, Response, Transport},
+    Error, MetaEnvelope, ResponseCode,
+};
+use futures::lock::Mutex;
+use std::{
+    convert::TryFrom,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// How requests that failed for a transient reason are retried.
+///
+/// Transport errors and server errors (HTTP 5xx) are only retried for requests that can
+/// safely be repeated, which excludes those that create something, such as saving a PTZ
+/// preset. Requests the server turned away with [`ResponseCode::RateLimited`] were not
+/// carried out and are always retried. Retries wait for an exponentially growing backoff
+/// with full jitter, and [`Error::RetriesExhausted`] is returned once none are left.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        RetryPolicy::default()
+    }
+    /// A policy that passes every failure straight to the caller.
+    pub fn never() -> Self {
+        RetryPolicy::default().max_retries(0)
+    }
+    /// Sets how often a request is retried after its first attempt.
+    pub fn max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+    /// Sets the backoff before the first retry, which doubles with every further retry
+    /// up to `max`.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self
+    }
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .checked_mul(1 << retry.min(16))
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff));
+        backoff.mul_f64(fastrand::f64())
+    }
+}
+
+/// Spaces requests out so that at most one starts per interval.
+#[derive(Debug)]
+struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    async fn acquire(&self, transport: &dyn Transport) {
+        let start = {
+            let mut next = self.next.lock().await;
+            let start = next.map_or_else(Instant::now, |next| next.max(Instant::now()));
+            *next = Some(start + self.interval);
+            start
+        };
+        let now = Instant::now();
+        if start > now {
+            transport.sleep(start - now).await;
+        }
+    }
+}
+
+/// Sends requests through a transport, applying the rate limit and retry policy.
+#[derive(Debug, Clone)]
+pub(crate) struct Client {
+    transport: Arc<dyn Transport>,
+    retry_policy: RetryPolicy,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+fn rate_limited(response: &Response) -> bool {
+    matches!(
+        serde_json::from_slice::<MetaEnvelope>(&response.body),
+        Ok(envelope) if envelope.meta.code == ResponseCode::RateLimited
+    )
+}
+
+impl Client {
+    pub(crate) fn new(
+        transport: Arc<dyn Transport>,
+        retry_policy: RetryPolicy,
+        rate_limit: Duration,
+    ) -> Self {
+        Client {
+            transport,
+            retry_policy,
+            rate_limiter: Arc::new(RateLimiter {
+                interval: rate_limit,
+                next: Mutex::new(None),
+            }),
+        }
+    }
+    /// Waits for `duration` on the transport's timer.
+    pub(crate) async fn sleep(&self, duration: Duration) {
+        self.transport.sleep(duration).await;
+    }
+    /// Sends a request, retrying transient failures if the request is `idempotent`.
+    pub(crate) async fn send(&self, request: Request, idempotent: bool) -> Result<Response, Error> {
+        let mut retries = 0;
+        loop {
+            self.rate_limiter.acquire(&*self.transport).await;
+            let outcome = self.transport.send(request.clone()).await;
+            let retry = match &outcome {
+                Ok(response) if rate_limited(response) => true,
+                Ok(response) => idempotent && response.status >= 500,
+                Err(Error::Transport(_)) => idempotent,
+                Err(_) => false,
+            };
+            if !retry || self.retry_policy.max_retries == 0 {
+                return outcome;
+            }
+            if retries == self.retry_policy.max_retries {
+                let last = match outcome {
+                    Ok(response) if rate_limited(&response) => {
+                        serde_json::from_slice::<MetaEnvelope>(&response.body)?
+                            .meta
+                            .into_error()
+                    }
+                    Ok(response) => Error::Http(surf::Error::from_str(
+                        surf::StatusCode::try_from(response.status)?,
+                        format!("server error for {}", request.url),
+                    )),
+                    Err(error) => error,
+                };
+                Err(Error::RetriesExhausted {
+                    attempts: retries + 1,
+                    last: Box::new(last),
+                })?;
+            }
+            self.sleep(self.retry_policy.delay(retries)).await;
+            retries += 1;
+        }
+    }
+}
@@ -1,22 +1,52 @@
-use ezviz::{camera_stream, EzvizApi};
+use ezviz::{camera_stream, discover_sadp, EzvizApi};
 use futures::StreamExt;
 use smol::block_on;
-use std::env;
+use std::{env, time::Duration};
+
+/// Resumes the session saved by the previous run, logging in with the password from the
+/// environment only when there is none or the server rejected it.
+async fn connect() -> EzvizApi {
+    EzvizApi::resume_or_login(
+        env::var("EZVIZ_SESSION_FILE").unwrap_or_else(|_| "ezviz_session.json".to_owned()),
+        env::var("EZVIZ_ACCOUNT").expect("no EZVIZ_ACCOUNT env var specified"),
+        env::var("EZVIZ_PASSWORD").expect("no EZVIZ_PASSWORD env var specified"),
+    )
+    .await
+    .unwrap()
+}
 
 fn main() {
     block_on(async {
-        let api = EzvizApi::connect(
-            env::var("EZVIZ_ACCOUNT").expect("no EZVIZ_ACCOUNT env var specified"),
-            env::var("EZVIZ_PASSWORD").expect("no EZVIZ_PASSWORD env var specified"),
-        )
-        .await
-        .unwrap();
-        let addr = api.devices().await.unwrap().first().unwrap().addr;
-        let mut images = camera_stream(
-            addr,
-            env::var("EZVIZ_VERIFICATION_CODE")
-                .expect("no EZVIZ_VERIFICATION_CODE env var specified"),
-        );
+        // Without an account, look for the camera on the local network instead.
+        let (addr, verification_code) = if env::var("EZVIZ_ACCOUNT").is_ok() {
+            let api = connect().await;
+            let (serial, addr) = api
+                .devices()
+                .await
+                .unwrap()
+                .into_iter()
+                .find_map(|device| Some((device.serial, device.addr?)))
+                .expect("no device with a known local address");
+            let verification_code = match env::var("EZVIZ_VERIFICATION_CODE") {
+                Ok(code) => code,
+                Err(_) => api.verification_code(&serial, None).await.unwrap(),
+            };
+            (addr, verification_code)
+        } else {
+            let addr = discover_sadp(Duration::from_secs(3))
+                .await
+                .unwrap()
+                .into_iter()
+                .find(|device| device.activated != Some(false))
+                .expect("no camera found on the local network")
+                .addr;
+            (
+                addr,
+                env::var("EZVIZ_VERIFICATION_CODE")
+                    .expect("no EZVIZ_VERIFICATION_CODE env var specified"),
+            )
+        };
+        let mut images = camera_stream(addr, verification_code);
         while let Some(image) = images.next().await {
             image.save("test.png").unwrap();
         }
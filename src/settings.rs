@@ -0,0 +1,382 @@
+use crate::{transport::Request, Error, EzvizApi, SwitchType};
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize, Serialize,
+};
+use serde_json::{Map, Value};
+use std::convert::TryFrom;
+
+/// Keys of the device configuration the settings below are stored under.
+const NIGHT_VISION_KEY: &str = "NightVision_Model";
+const DETECTION_AREA_KEY: &str = "Alarm_DetectArea";
+const OSD_KEY: &str = "display_OSD";
+
+/// Algorithm type of motion detection in the algorithm configuration.
+const MOTION_DETECTION: i32 = 0;
+/// Highest motion detection sensitivity.
+const MAX_SENSITIVITY: u8 = 6;
+/// Widest detection area, as each row is sent as the bits of a 64 bit mask.
+const MAX_DETECTION_COLUMNS: usize = 64;
+
+/// How the picture is shot in the dark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NightVision {
+    /// Infrared light and a black and white picture.
+    BlackAndWhite,
+    /// The spotlight and a colour picture.
+    Colour,
+    /// Black and white until motion is detected, then colour.
+    Smart,
+    Other(i32),
+}
+
+impl NightVision {
+    pub fn value(self) -> i32 {
+        match self {
+            NightVision::BlackAndWhite => 0,
+            NightVision::Colour => 1,
+            NightVision::Smart => 2,
+            NightVision::Other(value) => value,
+        }
+    }
+}
+
+impl From<i32> for NightVision {
+    fn from(value: i32) -> Self {
+        match value {
+            0 => NightVision::BlackAndWhite,
+            1 => NightVision::Colour,
+            2 => NightVision::Smart,
+            value => NightVision::Other(value),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NightVisionConfig {
+    graphic_type: i32,
+    #[serde(default = "full_luminance")]
+    luminance: u8,
+    /// Other fields, which are written back unchanged.
+    #[serde(flatten)]
+    other: Map<String, Value>,
+}
+
+fn full_luminance() -> u8 {
+    100
+}
+
+/// Which way to flip the picture of a camera that is mounted differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Flip {
+    /// Mirrors the picture.
+    #[serde(rename = "LEFT_RIGHT")]
+    Horizontal,
+    #[serde(rename = "UP_DOWN")]
+    Vertical,
+    /// Rotates the picture by 180 degrees, for cameras mounted upside down.
+    #[serde(rename = "CENTER")]
+    Both,
+}
+
+/// The part of the picture motion is detected in, as a grid of cells that are either
+/// watched or ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DetectionArea {
+    rows: usize,
+    columns: usize,
+    cells: Vec<bool>,
+}
+
+impl DetectionArea {
+    /// Creates an area of the given size that covers the whole picture.
+    pub fn full(rows: usize, columns: usize) -> Self {
+        DetectionArea {
+            rows,
+            columns,
+            cells: vec![true; rows * columns],
+        }
+    }
+    /// Creates an area of the given size that covers nothing.
+    pub fn empty(rows: usize, columns: usize) -> Self {
+        DetectionArea {
+            rows,
+            columns,
+            cells: vec![false; rows * columns],
+        }
+    }
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+    /// Returns whether motion in a cell is detected. Cells outside the grid are not.
+    pub fn get(&self, row: usize, column: usize) -> bool {
+        row < self.rows && column < self.columns && self.cells[row * self.columns + column]
+    }
+    /// Sets whether motion in a cell is detected.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cell is outside the grid.
+    pub fn set(&mut self, row: usize, column: usize, watched: bool) {
+        assert!(
+            row < self.rows && column < self.columns,
+            "cell outside the grid"
+        );
+        self.cells[row * self.columns + column] = watched;
+    }
+}
+
+/// Wire format of a detection area, where each row is a bit mask with the first column
+/// in the most significant of the row's bits.
+#[derive(Debug, Serialize, Deserialize)]
+struct DetectionAreaConfig {
+    row: usize,
+    column: usize,
+    area: Vec<u64>,
+}
+
+impl TryFrom<&DetectionArea> for DetectionAreaConfig {
+    type Error = Error;
+
+    fn try_from(area: &DetectionArea) -> Result<Self, Error> {
+        if area.columns > MAX_DETECTION_COLUMNS {
+            Err(Error::InvalidArgument(format!(
+                "detection areas have at most {} columns, not {}",
+                MAX_DETECTION_COLUMNS, area.columns
+            )))?;
+        }
+        Ok(DetectionAreaConfig {
+            row: area.rows,
+            column: area.columns,
+            area: area
+                .cells
+                .chunks(area.columns.max(1))
+                .map(|row| {
+                    row.iter()
+                        .fold(0, |mask, &watched| mask << 1 | watched as u64)
+                })
+                .collect(),
+        })
+    }
+}
+
+impl From<DetectionAreaConfig> for DetectionArea {
+    fn from(config: DetectionAreaConfig) -> Self {
+        let mut area = DetectionArea::empty(config.row, config.column);
+        for (row, mask) in config.area.iter().take(config.row).enumerate() {
+            for column in 0..config.column {
+                let shift = (config.column - 1 - column) as u32;
+                area.cells[row * config.column + column] =
+                    mask.checked_shr(shift).unwrap_or(0) & 1 == 1;
+            }
+        }
+        area
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OsdConfig {
+    enable: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfigResponse {
+    data: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ConfigUpdate<'a> {
+    key: &'a str,
+    value: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AlgorithmQuery<'a> {
+    sub_serial: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AlgorithmUpdate<'a> {
+    sub_serial: &'a str,
+    #[serde(rename = "type")]
+    kind: i32,
+    channel_no: u32,
+    value: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct Algorithm {
+    #[serde(rename = "type")]
+    kind: i32,
+    value: u8,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AlgorithmList {
+    algorithm_list: Vec<Algorithm>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AlgorithmResponse {
+    algorithm_config: AlgorithmList,
+}
+
+#[derive(Debug, Serialize)]
+struct MirrorCommand {
+    command: Flip,
+}
+
+/// Reading and changing the picture and motion detection settings of cameras.
+impl EzvizApi {
+    async fn config<T: DeserializeOwned>(&self, serial: &str, key: &str) -> Result<T, Error> {
+        let data = self
+            .call::<ConfigResponse, _>(true, |session| {
+                Ok(Request::get(self.endpoint.url(
+                    &session.api_domain,
+                    &format!("/v3/devconfig/v1/keyValue/{}/1/{}", serial, key),
+                )))
+            })
+            .await?
+            .data;
+        Ok(serde_json::from_str(&data)?)
+    }
+    async fn set_config<T: Serialize>(
+        &self,
+        serial: &str,
+        key: &str,
+        value: &T,
+    ) -> Result<(), Error> {
+        let value = serde_json::to_string(value)?;
+        self.call::<IgnoredAny, _>(true, |session| {
+            Request::put(self.endpoint.url(
+                &session.api_domain,
+                &format!("/v3/devconfig/v1/keyValue/{}/1/op", serial),
+            ))
+            .form(&ConfigUpdate { key, value: &value })
+        })
+        .await?;
+        Ok(())
+    }
+    /// Returns the motion detection sensitivity of the camera, from 1 (lowest) to 6, or 0
+    /// if motion detection is off.
+    pub async fn motion_sensitivity(&self, serial: &str) -> Result<u8, Error> {
+        self.call_legacy::<AlgorithmResponse, _>(true, |session| {
+            Request::post(
+                self.endpoint
+                    .url(&session.api_domain, "/api/device/queryAlgorithmConfig"),
+            )
+            .form(&AlgorithmQuery { sub_serial: serial })
+        })
+        .await?
+        .algorithm_config
+        .algorithm_list
+        .into_iter()
+        .find(|algorithm| algorithm.kind == MOTION_DETECTION)
+        .map(|algorithm| algorithm.value)
+        .ok_or(Error::Unsupported)
+    }
+    /// Sets the motion detection sensitivity from 1 (lowest) to 6, or turns motion
+    /// detection off with 0. Other values fail with [`Error::InvalidArgument`].
+    pub async fn set_motion_sensitivity(&self, serial: &str, sensitivity: u8) -> Result<(), Error> {
+        if sensitivity > MAX_SENSITIVITY {
+            Err(Error::InvalidArgument(format!(
+                "motion sensitivity ranges from 0 to {}, not {}",
+                MAX_SENSITIVITY, sensitivity
+            )))?;
+        }
+        self.call_legacy::<IgnoredAny, _>(true, |session| {
+            Request::post(
+                self.endpoint
+                    .url(&session.api_domain, "/api/device/configAlgorithm"),
+            )
+            .form(&AlgorithmUpdate {
+                sub_serial: serial,
+                kind: MOTION_DETECTION,
+                channel_no: 1,
+                value: sensitivity,
+            })
+        })
+        .await?;
+        Ok(())
+    }
+    pub async fn detection_area(&self, serial: &str) -> Result<DetectionArea, Error> {
+        Ok(self
+            .config::<DetectionAreaConfig>(serial, DETECTION_AREA_KEY)
+            .await?
+            .into())
+    }
+    /// Sets the part of the picture motion is detected in. Areas wider than 64 columns
+    /// fail with [`Error::InvalidArgument`].
+    pub async fn set_detection_area(
+        &self,
+        serial: &str,
+        area: &DetectionArea,
+    ) -> Result<(), Error> {
+        self.set_config(
+            serial,
+            DETECTION_AREA_KEY,
+            &DetectionAreaConfig::try_from(area)?,
+        )
+        .await
+    }
+    /// Returns whether alarms are only raised for motion of human shapes, or `None` if the
+    /// camera cannot tell people apart.
+    pub async fn human_detection(&self, serial: &str) -> Result<Option<bool>, Error> {
+        self.switch(serial, SwitchType::HumanDetection).await
+    }
+    pub async fn set_human_detection(&self, serial: &str, enable: bool) -> Result<(), Error> {
+        self.set_switch(serial, SwitchType::HumanDetection, enable)
+            .await
+    }
+    /// Flips the picture of the camera. The camera does not report its orientation, so
+    /// flipping twice the same way restores the picture.
+    pub async fn flip_image(&self, serial: &str, flip: Flip) -> Result<(), Error> {
+        self.call::<IgnoredAny, _>(false, |session| {
+            Request::put(self.endpoint.url(
+                &session.api_domain,
+                &format!("/v3/devices/{}/1/mirror", serial),
+            ))
+            .form(&MirrorCommand { command: flip })
+        })
+        .await?;
+        Ok(())
+    }
+    pub async fn night_vision(&self, serial: &str) -> Result<NightVision, Error> {
+        Ok(self
+            .config::<NightVisionConfig>(serial, NIGHT_VISION_KEY)
+            .await?
+            .graphic_type
+            .into())
+    }
+    /// Sets the night vision mode, keeping the brightness and other night vision
+    /// settings as they are.
+    pub async fn set_night_vision(&self, serial: &str, mode: NightVision) -> Result<(), Error> {
+        let mut config = self
+            .config::<NightVisionConfig>(serial, NIGHT_VISION_KEY)
+            .await?;
+        config.graphic_type = mode.value();
+        self.set_config(serial, NIGHT_VISION_KEY, &config).await
+    }
+    /// Returns whether the date and time are overlaid on the picture.
+    pub async fn osd(&self, serial: &str) -> Result<bool, Error> {
+        Ok(self.config::<OsdConfig>(serial, OSD_KEY).await?.enable != 0)
+    }
+    pub async fn set_osd(&self, serial: &str, enable: bool) -> Result<(), Error> {
+        self.set_config(
+            serial,
+            OSD_KEY,
+            &OsdConfig {
+                enable: enable as u8,
+            },
+        )
+        .await
+    }
+}
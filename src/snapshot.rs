@@ -0,0 +1,31 @@
+use crate::{transport::Request, Error, EzvizApi};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CaptureResponse {
+    capture_url: String,
+}
+
+impl EzvizApi {
+    /// Has the device take a picture and upload it to the cloud, returning the decoded
+    /// image.
+    ///
+    /// Unlike [`camera_stream`](crate::camera_stream) this does not need access to the
+    /// local network of the camera and does not decode video.
+    pub async fn capture_snapshot(
+        &self,
+        serial: &str,
+    ) -> Result<image::ImageBuffer<image::Rgb<u8>, Vec<u8>>, Error> {
+        let url = self
+            .call::<CaptureResponse, _>(false, |session| {
+                Ok(Request::put(self.endpoint.url(
+                    &session.api_domain,
+                    &format!("/v3/devices/{}/1/capture", serial),
+                )))
+            })
+            .await?
+            .capture_url;
+        Ok(image::load_from_memory(&self.download(&url).await?)?.into_rgb8())
+    }
+}
@@ -0,0 +1,44 @@
+use crate::{transport::Request, Error, EzvizApi, SwitchType};
+use serde::de::IgnoredAny;
+
+/// Reading and toggling device switches such as privacy mode or the status LED.
+impl EzvizApi {
+    /// Returns whether a switch of the device is enabled, or `None` if the device does not
+    /// have that switch.
+    pub async fn switch(&self, serial: &str, kind: SwitchType) -> Result<Option<bool>, Error> {
+        Ok(self.device(serial).await?.switch(kind))
+    }
+    pub async fn set_switch(
+        &self,
+        serial: &str,
+        kind: SwitchType,
+        enable: bool,
+    ) -> Result<(), Error> {
+        self.call::<IgnoredAny, _>(true, |session| {
+            Ok(Request::put(self.endpoint.url(
+                &session.api_domain,
+                &format!(
+                    "/v3/devices/{}/1/{}/{}/switchStatus",
+                    serial,
+                    enable as u8,
+                    kind.value()
+                ),
+            )))
+        })
+        .await?;
+        Ok(())
+    }
+    /// Puts the camera to sleep, which stops recording and hides the lens where the model
+    /// allows it, or wakes it up again.
+    pub async fn set_privacy(&self, serial: &str, enable: bool) -> Result<(), Error> {
+        self.set_switch(serial, SwitchType::Privacy, enable).await
+    }
+    pub async fn set_status_light(&self, serial: &str, enable: bool) -> Result<(), Error> {
+        self.set_switch(serial, SwitchType::StatusLight, enable)
+            .await
+    }
+    pub async fn set_infrared(&self, serial: &str, enable: bool) -> Result<(), Error> {
+        self.set_switch(serial, SwitchType::InfraredLight, enable)
+            .await
+    }
+}
@@ -51,6 +51,23 @@ fn make_predicate(query: String) -> Box<dyn FnMut(DateTime<Utc>) -> bool + Send>
         .unwrap_or(Box::new(|_| false) as Box<dyn FnMut(_) -> _ + Send>)
 }
 
+/// Resumes the session saved by the previous run, logging in with the password from the
+/// environment only when there is none or the server rejected it.
+async fn connect() -> EzvizApi {
+    let builder = EzvizApi::builder();
+    // Keep the client's requests on the bot's tokio runtime when hyper is available.
+    #[cfg(feature = "hyper-client")]
+    let builder = builder.transport(ezviz::transport::HyperTransport::new());
+    builder
+        .resume_or_login(
+            env::var("EZVIZ_SESSION_FILE").unwrap_or_else(|_| "ezviz_session.json".to_owned()),
+            env::var("EZVIZ_ACCOUNT").expect("no EZVIZ_ACCOUNT env var specified"),
+            env::var("EZVIZ_PASSWORD").expect("no EZVIZ_PASSWORD env var specified"),
+        )
+        .await
+        .unwrap()
+}
+
 #[tokio::main]
 async fn main() {
     let db = Arc::new(Mutex::new(sled::open("photo_ids").unwrap()));
@@ -400,27 +417,30 @@ async fn main() {
             }
         }
     });
-    let addr = {
-        let api = EzvizApi::connect(
-            env::var("EZVIZ_ACCOUNT").expect("no EZVIZ_ACCOUNT env var specified"),
-            env::var("EZVIZ_PASSWORD").expect("no EZVIZ_PASSWORD env var specified"),
-        )
-        .await
-        .unwrap();
-        api.devices().await.unwrap().first().unwrap().addr
+    let (addr, verification_code) = {
+        let api = connect().await;
+        let (serial, addr) = api
+            .devices()
+            .await
+            .unwrap()
+            .into_iter()
+            .find_map(|device| Some((device.serial, device.addr?)))
+            .expect("no device with a known local address");
+        let verification_code = match env::var("EZVIZ_VERIFICATION_CODE") {
+            Ok(code) => code,
+            Err(_) => api.verification_code(&serial, None).await.unwrap(),
+        };
+        (addr, verification_code)
     };
-    let images = camera_stream(
-        addr,
-        env::var("EZVIZ_VERIFICATION_CODE").expect("no EZVIZ_VERIFICATION_CODE env var specified"),
-    )
-    .enumerate()
-    .filter_map(|(index, image)| async move {
-        if index % frequency == 0 {
-            Some(image)
-        } else {
-            None
-        }
-    });
+    let images = camera_stream(addr, verification_code)
+        .enumerate()
+        .filter_map(|(index, image)| async move {
+            if index % frequency == 0 {
+                Some(image)
+            } else {
+                None
+            }
+        });
     pin_mut!(images);
     while let Some(image) = images.next().await {
         let mut png_data = Vec::new();
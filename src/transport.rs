@@ -0,0 +1,375 @@
+//! HTTP transports the client sends its requests through.
+//!
+//! [`SurfTransport`] is used unless another one is set with
+//! [`EzvizApiBuilder::transport`](crate::EzvizApiBuilder::transport). Applications running
+//! on tokio can enable the `hyper-client` feature and use [`HyperTransport`] instead, so
+//! that requests and the waits between them run on their own runtime rather than surf's
+//! `async-std` executor and smol's timer thread.
+
+use crate::Error;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    fmt::Debug,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// HTTP method of a [`Request`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Method {
+    Get,
+    Post,
+    Put,
+    Delete,
+}
+
+impl Method {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+        }
+    }
+}
+
+/// A request to be sent by a [`Transport`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Request {
+    pub method: Method,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn new<T: Into<String>>(method: Method, url: T) -> Self {
+        Request {
+            method,
+            url: url.into(),
+            headers: vec![],
+            body: vec![],
+        }
+    }
+    pub fn get<T: Into<String>>(url: T) -> Self {
+        Request::new(Method::Get, url)
+    }
+    pub fn post<T: Into<String>>(url: T) -> Self {
+        Request::new(Method::Post, url)
+    }
+    pub fn put<T: Into<String>>(url: T) -> Self {
+        Request::new(Method::Put, url)
+    }
+    pub fn delete<T: Into<String>>(url: T) -> Self {
+        Request::new(Method::Delete, url)
+    }
+    pub fn header<T: Into<String>, U: Into<String>>(mut self, name: T, value: U) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+    /// Appends the URL encoded fields of `query` to the query string.
+    pub fn query<T: Serialize>(mut self, query: &T) -> Result<Self, Error> {
+        let query = serde_urlencoded::to_string(query)?;
+        if !query.is_empty() {
+            self.url
+                .push(if self.url.contains('?') { '&' } else { '?' });
+            self.url.push_str(&query);
+        }
+        Ok(self)
+    }
+    /// Sets the body, along with its content type.
+    pub fn body<T: Into<Vec<u8>>>(mut self, content_type: &str, body: T) -> Self {
+        self.body = body.into();
+        self.header("Content-Type", content_type)
+    }
+    /// Sets the body to the URL encoded fields of `form`.
+    pub fn form<T: Serialize>(self, form: &T) -> Result<Self, Error> {
+        let form = serde_urlencoded::to_string(form)?;
+        Ok(self.body("application/x-www-form-urlencoded", form))
+    }
+}
+
+/// Status, headers and body of a response received by a [`Transport`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Response {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Returns the value of a header, matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+    /// Fails with [`Error::Http`] carrying the status and `message` unless the status is
+    /// 2xx.
+    pub(crate) fn check_status<T: Into<String>>(&self, message: T) -> Result<(), Error> {
+        if !(200..300).contains(&self.status) {
+            Err(surf::Error::from_str(
+                surf::StatusCode::try_from(self.status)?,
+                message.into(),
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// Sends HTTP requests on behalf of [`EzvizApi`](crate::EzvizApi).
+///
+/// Implementations only fail for errors that prevent getting a response at all, which
+/// they report as [`Error::Transport`] so that idempotent requests are retried. HTTP error
+/// statuses are returned as a [`Response`].
+pub trait Transport: Debug + Send + Sync {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>>;
+    /// Waits for `duration`, such as a retry backoff or the rate limit between requests.
+    ///
+    /// The default uses smol's timer, which starts a thread of its own on first use.
+    /// Transports tied to a runtime should use its timer instead.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            smol::Timer::after(duration).await;
+        })
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        (**self).send(request)
+    }
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        (**self).sleep(duration)
+    }
+}
+
+/// Transport based on surf, which works with any runtime but brings up its own
+/// `async-std` executor threads.
+#[derive(Debug, Default)]
+pub struct SurfTransport {
+    client: surf::Client,
+}
+
+impl SurfTransport {
+    pub fn new() -> Self {
+        SurfTransport::default()
+    }
+}
+
+impl Transport for SurfTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        Box::pin(async move {
+            let method = match request.method {
+                Method::Get => surf::http::Method::Get,
+                Method::Post => surf::http::Method::Post,
+                Method::Put => surf::http::Method::Put,
+                Method::Delete => surf::http::Method::Delete,
+            };
+            let url =
+                surf::Url::parse(&request.url).map_err(|error| Error::Transport(error.into()))?;
+            let mut builder = surf::RequestBuilder::new(method, url);
+            for (name, value) in &request.headers {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+            let content_type = request
+                .headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("Content-Type"))
+                .map(|(_, value)| value.clone());
+            let mut body = surf::Body::from_bytes(request.body);
+            if let Some(content_type) = content_type {
+                body.set_mime(
+                    content_type
+                        .parse::<surf::http::Mime>()
+                        .map_err(surf_error)?,
+                );
+            }
+            let mut response = self
+                .client
+                .send(builder.body(body))
+                .await
+                .map_err(surf_error)?;
+            let headers = response
+                .iter()
+                .flat_map(|(name, values)| {
+                    values
+                        .iter()
+                        .map(move |value| (name.to_string(), value.to_string()))
+                })
+                .collect();
+            Ok(Response {
+                status: response.status().into(),
+                headers,
+                body: response.body_bytes().await.map_err(surf_error)?,
+            })
+        })
+    }
+}
+
+pub(crate) fn surf_error(error: surf::Error) -> Error {
+    Error::Transport(error.into())
+}
+
+/// Transport based on hyper and hyper-tls, for applications running on tokio.
+///
+/// Requests have to be sent from within a tokio runtime.
+#[cfg(feature = "hyper-client")]
+#[derive(Debug)]
+pub struct HyperTransport {
+    client: hyper::Client<hyper_tls::HttpsConnector<hyper::client::HttpConnector>>,
+}
+
+#[cfg(feature = "hyper-client")]
+impl HyperTransport {
+    pub fn new() -> Self {
+        HyperTransport {
+            client: hyper::Client::builder().build(hyper_tls::HttpsConnector::new()),
+        }
+    }
+}
+
+#[cfg(feature = "hyper-client")]
+impl Default for HyperTransport {
+    fn default() -> Self {
+        HyperTransport::new()
+    }
+}
+
+#[cfg(feature = "hyper-client")]
+impl Transport for HyperTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        Box::pin(async move {
+            let mut builder = hyper::Request::builder()
+                .method(request.method.as_str())
+                .uri(request.url.as_str());
+            for (name, value) in &request.headers {
+                builder = builder.header(name.as_str(), value.as_str());
+            }
+            let request = builder
+                .body(hyper::Body::from(request.body))
+                .map_err(|error| Error::Transport(error.into()))?;
+            let response = self
+                .client
+                .request(request)
+                .await
+                .map_err(|error| Error::Transport(error.into()))?;
+            let status = response.status().as_u16();
+            let headers = response
+                .headers()
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_owned()))
+                })
+                .collect();
+            let body = hyper::body::to_bytes(response.into_body())
+                .await
+                .map_err(|error| Error::Transport(error.into()))?;
+            Ok(Response {
+                status,
+                headers,
+                body: body.to_vec(),
+            })
+        })
+    }
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(tokio::time::delay_for(duration))
+    }
+}
+
+/// A request and the response it received, as recorded by [`RecordingTransport`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Exchange {
+    pub request: Request,
+    pub response: Response,
+}
+
+/// Transport that passes requests on to another transport and keeps a copy of every
+/// exchange, e.g. to save them as fixtures for a [`ReplayTransport`].
+#[derive(Debug)]
+pub struct RecordingTransport<T> {
+    inner: T,
+    exchanges: Mutex<Vec<Exchange>>,
+}
+
+impl<T: Transport> RecordingTransport<T> {
+    pub fn new(inner: T) -> Self {
+        RecordingTransport {
+            inner,
+            exchanges: Mutex::new(vec![]),
+        }
+    }
+    /// Returns the exchanges recorded so far, in the order they completed.
+    pub fn exchanges(&self) -> Vec<Exchange> {
+        self.exchanges.lock().unwrap().clone()
+    }
+}
+
+impl<T: Transport> Transport for RecordingTransport<T> {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        Box::pin(async move {
+            let response = self.inner.send(request.clone()).await?;
+            self.exchanges.lock().unwrap().push(Exchange {
+                request,
+                response: response.clone(),
+            });
+            Ok(response)
+        })
+    }
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        self.inner.sleep(duration)
+    }
+}
+
+/// Transport that answers requests from previously recorded exchanges without any
+/// network access.
+///
+/// Each request is answered with the first unused exchange of the same method and URL,
+/// ignoring headers and body. Requests without one fail.
+#[derive(Debug)]
+pub struct ReplayTransport {
+    exchanges: Mutex<VecDeque<Exchange>>,
+}
+
+impl ReplayTransport {
+    pub fn new<T: IntoIterator<Item = Exchange>>(exchanges: T) -> Self {
+        ReplayTransport {
+            exchanges: Mutex::new(exchanges.into_iter().collect()),
+        }
+    }
+    /// Returns the number of exchanges that have not been replayed yet.
+    pub fn remaining(&self) -> usize {
+        self.exchanges.lock().unwrap().len()
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        let response = exchanges
+            .iter()
+            .position(|exchange| {
+                exchange.request.method == request.method && exchange.request.url == request.url
+            })
+            .and_then(|index| exchanges.remove(index))
+            .map(|exchange| exchange.response)
+            .ok_or_else(|| {
+                Error::Transport(
+                    format!(
+                        "no recorded response for {} {}",
+                        request.method.as_str(),
+                        request.url
+                    )
+                    .into(),
+                )
+            });
+        Box::pin(async move { response })
+    }
+}
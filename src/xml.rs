@@ -0,0 +1,140 @@
+//! Just enough XML reading for the small, regular documents cameras send: elements are
+//! found by their local name, ignoring namespace prefixes, and are assumed not to nest
+//! inside elements of the same name.
+
+/// An element found by [`elements`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Element<'a> {
+    attributes: &'a str,
+    pub(crate) content: &'a str,
+}
+
+impl<'a> Element<'a> {
+    /// Returns the value of an attribute, matched by its local name.
+    pub(crate) fn attribute(&self, name: &str) -> Option<&'a str> {
+        let mut rest = self.attributes;
+        while let Some(eq) = rest.find('=') {
+            let key = rest[..eq].trim();
+            let key = key.rsplit(':').next().unwrap_or(key);
+            let value = rest[eq + 1..].trim_start();
+            let quote = value.chars().next()?;
+            let end = value[1..].find(quote)? + 1;
+            if key == name {
+                return Some(&value[1..end]);
+            }
+            rest = &value[end + 1..];
+        }
+        None
+    }
+    /// Returns the trimmed text of the element, or `None` if it is empty.
+    pub(crate) fn text(&self) -> Option<&'a str> {
+        Some(self.content.trim()).filter(|text| !text.is_empty())
+    }
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+/// Returns every element with the given local name, in document order.
+pub(crate) fn elements<'a>(xml: &'a str, name: &str) -> Vec<Element<'a>> {
+    let mut found = vec![];
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let end = match rest.find('>') {
+            Some(end) => end,
+            None => break,
+        };
+        let tag = &rest[..end];
+        if tag.starts_with('/') || tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/');
+        let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+        let full_name = &tag[..name_end];
+        if local_name(full_name) != name {
+            continue;
+        }
+        let attributes = &tag[name_end..];
+        let body = &rest[end + 1..];
+        if self_closing {
+            found.push(Element {
+                attributes,
+                content: "",
+            });
+            rest = body;
+            continue;
+        }
+        match body.find(&format!("</{}>", full_name)) {
+            Some(close) => {
+                found.push(Element {
+                    attributes,
+                    content: &body[..close],
+                });
+                rest = &body[close..];
+            }
+            None => break,
+        }
+    }
+    found
+}
+
+/// Returns the first element with the given local name.
+pub(crate) fn element<'a>(xml: &'a str, name: &str) -> Option<Element<'a>> {
+    elements(xml, name).into_iter().next()
+}
+
+/// Returns the text of the first element with the given local name, if it has any.
+pub(crate) fn text<'a>(xml: &'a str, name: &str) -> Option<&'a str> {
+    element(xml, name)?.text()
+}
+
+/// Escapes text for use in element content or attribute values.
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Replaces the predefined entities and character references in text read from a
+/// document.
+pub(crate) fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) => end,
+            None => break,
+        };
+        let replacement = match &rest[1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            reference => reference
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| reference.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(std::char::from_u32),
+        };
+        match replacement {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
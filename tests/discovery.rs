@@ -0,0 +1,65 @@
+use ezviz::{discover_sadp_at, SADP_PORT};
+use smol::{block_on, net::UdpSocket};
+use std::time::Duration;
+
+const PROBE_MATCH: &str = r#"<?xml version="1.0" encoding="UTF-8" ?>
+<ProbeMatch>
+<Uuid>{uuid}</Uuid>
+<Types>inquiry</Types>
+<DeviceType>138752</DeviceType>
+<DeviceDescription>CS-C6N-A0-1C2WFR</DeviceDescription>
+<DeviceSN>CS-C6N-A0-1C2WFR20201027CCRRD12345678</DeviceSN>
+<CommandPort>8000</CommandPort>
+<HttpPort>80</HttpPort>
+<MAC>bc-ad-28-12-34-56</MAC>
+<IPv4Address>192.168.1.20</IPv4Address>
+<IPv4SubnetMask>255.255.255.0</IPv4SubnetMask>
+<IPv4Gateway>192.168.1.1</IPv4Gateway>
+<DHCP>true</DHCP>
+<SoftwareVersion>V5.3.0build 201027</SoftwareVersion>
+<Activated>true</Activated>
+</ProbeMatch>"#;
+
+#[test]
+fn discovers_devices_answering_probes() {
+    block_on(async {
+        let camera = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = camera.local_addr().unwrap();
+        let responder = smol::spawn(async move {
+            let mut buf = vec![0; 1024];
+            let (len, from) = camera.recv_from(&mut buf).await.unwrap();
+            let probe = String::from_utf8_lossy(&buf[..len]).into_owned();
+            assert!(probe.contains("<Types>inquiry</Types>"));
+            // Devices answer more than once, and other SADP traffic should be ignored.
+            for answer in &[PROBE_MATCH, PROBE_MATCH, probe.as_str()] {
+                camera.send_to(answer.as_bytes(), from).await.unwrap();
+            }
+            // Others answer to the multicast group instead of the sender.
+            let other = UdpSocket::bind("0.0.0.0:0").await.unwrap();
+            other
+                .send_to(
+                    PROBE_MATCH.replace("D12345678", "E87654321").as_bytes(),
+                    ("239.255.255.250", SADP_PORT),
+                )
+                .await
+                .unwrap();
+        });
+        let devices = discover_sadp_at(addr, Duration::from_millis(300))
+            .await
+            .unwrap();
+        responder.await;
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].serial, "D12345678");
+        assert_eq!(
+            devices[0].addr,
+            "192.168.1.20".parse::<std::net::IpAddr>().unwrap()
+        );
+        assert_eq!(devices[0].model.as_deref(), Some("CS-C6N-A0-1C2WFR"));
+        assert_eq!(
+            devices[0].firmware_version.as_deref(),
+            Some("V5.3.0build 201027")
+        );
+        assert_eq!(devices[0].activated, Some(true));
+        assert_eq!(devices[1].serial, "E87654321");
+    });
+}
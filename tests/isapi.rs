@@ -0,0 +1,243 @@
+use async_std::net::TcpListener;
+use ezviz::{
+    isapi::{AlertType, IsapiCamera},
+    Error,
+};
+use futures::StreamExt;
+use http_types::{Request, Response, StatusCode};
+use smol::block_on;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+const VERIFICATION_CODE: &str = "ABCDEF";
+const REALM: &str = "DS-2CD2032-I";
+const NONCE: &str = "4e6a45304e7a59314d54637a4f546c6a";
+
+const DEVICE_INFO: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<DeviceInfo version="2.0" xmlns="http://www.hikvision.com/ver20/XMLSchema">
+<deviceName>Front door</deviceName>
+<deviceID>88</deviceID>
+<model>CS-C6N-A0-1C2WFR</model>
+<serialNumber>CS-C6N-A0-1C2WFR20201027CCRRD12345678</serialNumber>
+<macAddress>bc:ad:28:12:34:56</macAddress>
+<firmwareVersion>V5.3.0</firmwareVersion>
+<firmwareReleasedDate>build 201027</firmwareReleasedDate>
+</DeviceInfo>"#;
+
+const MOTION_ALERT: &str = r#"<EventNotificationAlert version="2.0" xmlns="http://www.hikvision.com/ver20/XMLSchema">
+<ipAddress>192.168.1.20</ipAddress>
+<channelID>1</channelID>
+<dateTime>2020-12-01T13:00:05+01:00</dateTime>
+<activePostCount>1</activePostCount>
+<eventType>VMD</eventType>
+<eventState>active</eventState>
+<eventDescription>Motion alarm</eventDescription>
+</EventNotificationAlert>"#;
+
+const HEARTBEAT: &str = r#"<EventNotificationAlert version="2.0" xmlns="http://www.hikvision.com/ver20/XMLSchema">
+<channelID>1</channelID>
+<dateTime>2020-12-01T13:00:10+01:00</dateTime>
+<eventType>videoloss</eventType>
+<eventState>inactive</eventState>
+<eventDescription>videoloss alarm</eventDescription>
+</EventNotificationAlert>"#;
+
+#[derive(Default)]
+struct State {
+    /// Number of requests answered with a digest challenge.
+    challenges: usize,
+}
+
+/// Returns the parameters of a digest `Authorization` header.
+fn digest_params(header: &str) -> HashMap<String, String> {
+    header
+        .trim_start_matches("Digest ")
+        .split(", ")
+        .filter_map(|param| {
+            let mut param = param.splitn(2, '=');
+            Some((
+                param.next()?.to_owned(),
+                param.next()?.trim_matches('"').to_owned(),
+            ))
+        })
+        .collect()
+}
+
+fn authorized(request: &Request) -> bool {
+    let header = match request.header("Authorization") {
+        Some(header) => header.last().as_str(),
+        None => return false,
+    };
+    let params = digest_params(header);
+    let param = |name: &str| params.get(name).map(String::as_str).unwrap_or_default();
+    let ha1 = md5::compute(format!("admin:{}:{}", REALM, VERIFICATION_CODE));
+    let ha2 = md5::compute(format!("GET:{}", request.url().path()));
+    let expected = md5::compute(format!(
+        "{:x}:{}:{}:{}:auth:{:x}",
+        ha1,
+        NONCE,
+        param("nc"),
+        param("cnonce"),
+        ha2
+    ));
+    param("username") == "admin"
+        && param("nonce") == NONCE
+        && param("uri") == request.url().path()
+        && param("response") == format!("{:x}", expected)
+}
+
+fn snapshot() -> Vec<u8> {
+    let mut jpeg = vec![];
+    image::DynamicImage::ImageRgb8(image::RgbImage::new(4, 2))
+        .write_to(&mut jpeg, image::ImageOutputFormat::Jpeg(90))
+        .unwrap();
+    jpeg
+}
+
+fn alert_stream() -> Vec<u8> {
+    let mut body = vec![];
+    body.extend_from_slice(
+        format!(
+            "--boundary\r\nContent-Type: application/xml; charset=\"UTF-8\"\r\nContent-Length: {}\r\n\r\n{}\r\n",
+            MOTION_ALERT.len(),
+            MOTION_ALERT
+        )
+        .as_bytes(),
+    );
+    // Some cameras attach a picture to the alert.
+    let picture = snapshot();
+    body.extend_from_slice(
+        format!(
+            "--boundary\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            picture.len()
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(&picture);
+    body.extend_from_slice(b"\r\n");
+    // Others leave out the length.
+    body.extend_from_slice(
+        format!(
+            "--boundary\r\nContent-Type: application/xml; charset=\"UTF-8\"\r\n\r\n{}\r\n--boundary\r\n",
+            HEARTBEAT
+        )
+        .as_bytes(),
+    );
+    body
+}
+
+async fn handle(state: Arc<Mutex<State>>, request: Request) -> http_types::Result<Response> {
+    if !authorized(&request) {
+        state.lock().unwrap().challenges += 1;
+        let mut response = Response::new(StatusCode::Unauthorized);
+        response.insert_header(
+            "WWW-Authenticate",
+            format!(
+                r#"Digest qop="auth", realm="{}", nonce="{}", stale="FALSE""#,
+                REALM, NONCE
+            ),
+        );
+        return Ok(response);
+    }
+    let mut response = Response::new(StatusCode::Ok);
+    match request.url().path() {
+        "/ISAPI/System/deviceInfo" => {
+            response.set_body(DEVICE_INFO);
+            response.insert_header("Content-Type", "application/xml");
+        }
+        "/ISAPI/Streaming/channels/101/picture" => {
+            response.set_body(snapshot());
+            response.insert_header("Content-Type", "image/jpeg");
+        }
+        "/ISAPI/Event/notification/alertStream" => {
+            response.set_body(alert_stream());
+            response.insert_header("Content-Type", "multipart/mixed; boundary=boundary");
+        }
+        _ => {
+            response.set_status(StatusCode::NotFound);
+            response.set_body(
+                "<ResponseStatus><statusCode>4</statusCode><statusString>Invalid Operation</statusString><subStatusCode>notSupport</subStatusCode></ResponseStatus>",
+            );
+        }
+    }
+    Ok(response)
+}
+
+async fn start() -> (SocketAddr, Arc<Mutex<State>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let state = Arc::new(Mutex::new(State::default()));
+    async_std::task::spawn({
+        let state = state.clone();
+        async move {
+            let mut incoming = listener.incoming();
+            while let Some(Ok(stream)) = incoming.next().await {
+                let state = state.clone();
+                async_std::task::spawn(async move {
+                    let _ = async_h1::accept(stream, move |request| handle(state.clone(), request))
+                        .await;
+                });
+            }
+        }
+    });
+    (addr, state)
+}
+
+fn camera(addr: SocketAddr, verification_code: &str) -> IsapiCamera {
+    IsapiCamera::new(addr.ip(), verification_code).port(addr.port())
+}
+
+#[test]
+fn reads_device_info_and_snapshots_with_digest_auth() {
+    block_on(async {
+        let (addr, state) = start().await;
+        let camera = camera(addr, VERIFICATION_CODE);
+        let info = camera.device_info().await.unwrap();
+        assert_eq!(info.name, "Front door");
+        assert_eq!(info.model, "CS-C6N-A0-1C2WFR");
+        assert_eq!(info.mac, "bc:ad:28:12:34:56");
+        assert_eq!(info.firmware_version, "V5.3.0");
+        let jpeg = camera.snapshot_jpeg().await.unwrap();
+        assert_eq!(&jpeg[..2], &[0xff, 0xd8]);
+        let picture = camera.snapshot().await.unwrap();
+        assert_eq!(picture.dimensions(), (4, 2));
+        // Only the first request is challenged, the others answer it up front.
+        assert_eq!(state.lock().unwrap().challenges, 1);
+        assert!(!format!("{:?}", camera).contains(VERIFICATION_CODE));
+    });
+}
+
+#[test]
+fn wrong_verification_code_fails_with_invalid_credentials() {
+    block_on(async {
+        let (addr, state) = start().await;
+        let camera = camera(addr, "WRONG1");
+        assert!(matches!(
+            camera.device_info().await,
+            Err(Error::InvalidCredentials)
+        ));
+        assert_eq!(state.lock().unwrap().challenges, 2);
+    });
+}
+
+#[test]
+fn streams_alerts_skipping_other_parts() {
+    block_on(async {
+        let (addr, _) = start().await;
+        let camera = camera(addr, VERIFICATION_CODE);
+        let alerts = camera.alerts().collect::<Vec<_>>().await;
+        assert_eq!(alerts.len(), 2);
+        let motion = alerts[0].as_ref().unwrap();
+        assert_eq!(motion.kind, AlertType::Motion);
+        assert!(motion.active);
+        assert_eq!(motion.channel, Some(1));
+        assert_eq!(motion.time.to_rfc3339(), "2020-12-01T12:00:05+00:00");
+        assert_eq!(motion.description.as_deref(), Some("Motion alarm"));
+        let heartbeat = alerts[1].as_ref().unwrap();
+        assert_eq!(heartbeat.kind, AlertType::VideoLoss);
+        assert!(!heartbeat.active);
+    });
+}
@@ -0,0 +1,403 @@
+//! A small fake of the EZVIZ cloud API that integration tests run against.
+//!
+//! Every request path is prefixed with the API subdomain it was sent to, which is what
+//! [`MockCloud::endpoint`] substitutes for `{}`. Logging in anywhere but [`REGION`]
+//! answers with the 1100 region redirect, just like the real service does.
+
+use async_std::net::TcpListener;
+use ezviz::Endpoint;
+use futures::StreamExt;
+use http_types::{Method, Request, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+pub const ACCOUNT: &str = "camera@example.com";
+pub const PASSWORD: &str = "correct horse battery staple";
+pub const REGION: &str = "apiius";
+
+pub struct State {
+    pub logins: usize,
+    pub sessions: Vec<String>,
+    /// Refresh tokens that can still be exchanged for a new session.
+    pub refresh_tokens: Vec<String>,
+    /// The refresh token each session was handed out with or renewed from.
+    session_tokens: HashMap<String, String>,
+    pub refreshes: usize,
+    pub devices: Value,
+    /// Number of upcoming requests answered with 503 Service Unavailable.
+    pub unavailable: usize,
+    /// Every request received, in order.
+    pub requests: usize,
+    /// Meta code and message returned by every authenticated call when set.
+    pub fail_with: Option<(i32, String)>,
+    /// Login verification code the account requires until the client has been verified.
+    pub verification_code: Option<String>,
+    pub codes_sent: usize,
+    /// Region that logins elsewhere are redirected to before reaching [`REGION`].
+    pub detour: Option<String>,
+    /// Canned bodies for any other authenticated route, keyed by method and route such
+    /// as `"PUT /v3/devices/D12345678/ptzControl"`. Bodies without `meta` succeed.
+    pub responses: HashMap<String, Value>,
+    /// Every call answered from `responses`, in order.
+    pub calls: Vec<Call>,
+    /// Files served without authentication from [`MockCloud::file_url`].
+    pub files: HashMap<String, Vec<u8>>,
+    /// Alarms served from the alarm query, honoring its time window and limit like the
+    /// real service. Unused while a canned response for that route is set.
+    pub alarms: Vec<Value>,
+    pub alarm_queries: usize,
+}
+
+pub struct Call {
+    /// Method and route, in the same format as the keys of [`State::responses`].
+    pub route: String,
+    /// Query string and form body parameters of the call.
+    pub params: HashMap<String, String>,
+}
+
+impl State {
+    pub fn respond<T: Into<String>>(&mut self, route: T, body: Value) {
+        self.responses.insert(route.into(), body);
+    }
+
+    /// Returns the parameters of the most recent call to `route`.
+    pub fn last_call(&self, route: &str) -> Option<&HashMap<String, String>> {
+        self.calls
+            .iter()
+            .rev()
+            .find(|call| call.route == route)
+            .map(|call| &call.params)
+    }
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            logins: 0,
+            sessions: vec![],
+            refresh_tokens: vec![],
+            session_tokens: HashMap::new(),
+            refreshes: 0,
+            devices: json!({
+                "cameraInfos": [
+                    { "cameraName": "Front door", "deviceSerial": "D12345678" },
+                    { "cameraName": "Garage", "deviceSerial": "E87654321" },
+                    { "cameraName": "Shed", "deviceSerial": "G11223344" },
+                ],
+                "deviceInfos": [
+                    {
+                        "deviceSerial": "D12345678",
+                        "deviceType": "CS-C6N-A0-1C2WFR",
+                        "version": "V5.3.0 build 201027",
+                        "status": 1,
+                        "defence": 1,
+                    },
+                    { "deviceSerial": "E87654321", "deviceType": "CS-C3W", "status": 2 },
+                ],
+                "wifiInfos": {
+                    "D12345678": { "netName": "office", "signal": 87 },
+                },
+                "switchStatusInfos": {
+                    "D12345678": [
+                        { "type": 7, "enable": false },
+                        { "type": 3, "enable": true },
+                    ],
+                },
+                "connectionInfos": {
+                    "D12345678": { "localIp": "192.168.1.20", "netIp": "203.0.113.7" },
+                    "E87654321": { "localIp": "", "netIp": "203.0.113.7" },
+                },
+            }),
+            unavailable: 0,
+            requests: 0,
+            fail_with: None,
+            verification_code: None,
+            codes_sent: 0,
+            detour: None,
+            responses: HashMap::new(),
+            calls: vec![],
+            files: HashMap::new(),
+            alarms: vec![],
+            alarm_queries: 0,
+        }
+    }
+}
+
+pub struct MockCloud {
+    addr: SocketAddr,
+    pub state: Arc<Mutex<State>>,
+}
+
+impl MockCloud {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+        async_std::task::spawn({
+            let state = state.clone();
+            async move {
+                let mut incoming = listener.incoming();
+                while let Some(Ok(stream)) = incoming.next().await {
+                    let state = state.clone();
+                    async_std::task::spawn(async move {
+                        let _ =
+                            async_h1::accept(stream, move |request| handle(state.clone(), request))
+                                .await;
+                    });
+                }
+            }
+        });
+        MockCloud { addr, state }
+    }
+
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint::new(format!("http://{}/{{}}", self.addr))
+    }
+
+    pub fn file_url(&self, name: &str) -> String {
+        format!("http://{}/files/{}", self.addr, name)
+    }
+
+    /// Invalidates every session handed out so far.
+    pub fn expire_sessions(&self) {
+        self.state.lock().unwrap().sessions.clear();
+    }
+
+    /// Invalidates every session and refresh token handed out so far.
+    pub fn revoke_sessions(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.sessions.clear();
+        state.refresh_tokens.clear();
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LoginForm {
+    account: String,
+    password: String,
+    #[serde(default)]
+    sms_code: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RefreshForm {
+    refresh_session_id: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AlarmQuery {
+    start_time: i64,
+    end_time: i64,
+    limit: usize,
+}
+
+#[derive(Deserialize)]
+struct PageQuery {
+    offset: usize,
+    limit: usize,
+}
+
+fn json_response(body: Value) -> Response {
+    let mut response = Response::new(StatusCode::Ok);
+    response.set_body(body);
+    response
+}
+
+fn meta(code: i32) -> Value {
+    json!({ "meta": { "code": code } })
+}
+
+async fn handle(state: Arc<Mutex<State>>, mut request: Request) -> http_types::Result<Response> {
+    let path = request.url().path().to_owned();
+    let mut segments = path.trim_start_matches('/').splitn(2, '/');
+    let domain = segments.next().unwrap_or_default().to_owned();
+    let route = format!("/{}", segments.next().unwrap_or_default());
+    let session_id = request
+        .header("sessionId")
+        .map(|values| values.as_str().to_owned());
+    let authorized = domain == REGION
+        && matches!(&session_id, Some(id) if state.lock().unwrap().sessions.contains(id));
+
+    {
+        let mut state = state.lock().unwrap();
+        state.requests += 1;
+        if state.unavailable > 0 {
+            state.unavailable -= 1;
+            return Ok(Response::new(StatusCode::ServiceUnavailable));
+        }
+    }
+
+    if domain == "files" {
+        let file = state
+            .lock()
+            .unwrap()
+            .files
+            .get(route.trim_start_matches('/'))
+            .cloned();
+        return Ok(match file {
+            Some(file) => {
+                let mut response = Response::new(StatusCode::Ok);
+                response.set_body(file);
+                response
+            }
+            None => Response::new(StatusCode::NotFound),
+        });
+    }
+
+    Ok(match (request.method(), route.as_str()) {
+        (Method::Post, "/v3/users/login") => {
+            let form: LoginForm = request.body_form().await?;
+            if form.account != ACCOUNT || form.password != format!("{:x}", md5::compute(PASSWORD)) {
+                return Ok(Response::new(StatusCode::BadRequest));
+            }
+            let mut state = state.lock().unwrap();
+            state.logins += 1;
+            if domain != REGION {
+                let target = match &state.detour {
+                    Some(detour) if *detour != domain => detour.as_str(),
+                    _ => REGION,
+                };
+                return Ok(json_response(json!({
+                    "meta": { "code": 1100 },
+                    "loginArea": { "apiDomain": format!("{}.ezvizlife.com", target) },
+                })));
+            }
+            if let Some(code) = state.verification_code.clone() {
+                match form.sms_code {
+                    Some(sms_code) if sms_code == code => state.verification_code = None,
+                    Some(_) => return Ok(json_response(meta(1012))),
+                    None => return Ok(json_response(meta(6002))),
+                }
+            }
+            let session_id = format!("session-{}", state.logins);
+            let refresh_token = format!("refresh-{}", state.logins);
+            state.sessions.push(session_id.clone());
+            state.refresh_tokens.push(refresh_token.clone());
+            state
+                .session_tokens
+                .insert(session_id.clone(), refresh_token.clone());
+            json_response(json!({
+                "meta": { "code": 200 },
+                "loginArea": { "apiDomain": format!("{}.ezvizlife.com", REGION) },
+                "loginSession": { "sessionId": session_id, "rfSessionId": refresh_token },
+            }))
+        }
+        (Method::Put, "/v3/apigateway/login") if domain == REGION => {
+            let form: RefreshForm = request.body_form().await?;
+            let mut state = state.lock().unwrap();
+            if !state.refresh_tokens.contains(&form.refresh_session_id) {
+                return Ok(json_response(meta(401)));
+            }
+            state.refreshes += 1;
+            let session_id = format!("refreshed-{}", state.refreshes);
+            state.sessions.push(session_id.clone());
+            state
+                .session_tokens
+                .insert(session_id.clone(), form.refresh_session_id);
+            json_response(json!({
+                "meta": { "code": 200 },
+                "sessionInfo": { "sessionId": session_id },
+            }))
+        }
+        (Method::Post, "/v3/sms/nologin/checkcode") => {
+            state.lock().unwrap().codes_sent += 1;
+            json_response(meta(200))
+        }
+        (_, _) if !authorized => json_response(meta(401)),
+        (_, _) if state.lock().unwrap().fail_with.is_some() => {
+            let (code, message) = state.lock().unwrap().fail_with.clone().unwrap();
+            json_response(json!({ "meta": { "code": code, "message": message } }))
+        }
+        (Method::Delete, "/v3/users/logout/v2") => {
+            // Logging out also revokes the refresh token of the session.
+            let session_id = session_id.unwrap_or_default();
+            let mut state = state.lock().unwrap();
+            state.sessions.retain(|id| *id != session_id);
+            if let Some(token) = state.session_tokens.remove(&session_id) {
+                state.refresh_tokens.retain(|known| *known != token);
+            }
+            json_response(meta(200))
+        }
+        (Method::Get, "/v3/userdevices/v1/devices/pagelist") => {
+            let query: PageQuery = request.query()?;
+            let mut body = state.lock().unwrap().devices.clone();
+            let cameras = body["cameraInfos"].as_array().cloned().unwrap_or_default();
+            // Pages count devices, which have a camera for each of their channels.
+            let mut serials = cameras
+                .iter()
+                .map(|camera| camera["deviceSerial"].clone())
+                .collect::<Vec<_>>();
+            serials.dedup();
+            let page = serials
+                .iter()
+                .skip(query.offset)
+                .take(query.limit)
+                .collect::<Vec<_>>();
+            body["page"] = json!({
+                "offset": query.offset,
+                "limit": query.limit,
+                "totalResults": serials.len(),
+                "hasNext": query.offset + page.len() < serials.len(),
+            });
+            body["cameraInfos"] = cameras
+                .into_iter()
+                .filter(|camera| page.contains(&&camera["deviceSerial"]))
+                .collect();
+            body["meta"] = meta(200)["meta"].clone();
+            json_response(body)
+        }
+        (Method::Get, "/v3/alarms/v2/advanced")
+            if !state
+                .lock()
+                .unwrap()
+                .responses
+                .contains_key("GET /v3/alarms/v2/advanced") =>
+        {
+            let query: AlarmQuery = request.query()?;
+            let mut state = state.lock().unwrap();
+            state.alarm_queries += 1;
+            let mut alarms = state
+                .alarms
+                .iter()
+                .filter(|alarm| {
+                    let time = alarm["alarmStartTime"].as_i64().unwrap_or_default();
+                    time >= query.start_time && time <= query.end_time
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            alarms.sort_by_key(|alarm| -alarm["alarmStartTime"].as_i64().unwrap_or_default());
+            alarms.truncate(query.limit);
+            json_response(json!({ "meta": { "code": 200 }, "alarms": alarms }))
+        }
+        (method, route) => {
+            let route = format!("{} {}", method, route);
+            let body = state.lock().unwrap().responses.get(&route).cloned();
+            match body {
+                Some(mut body) => {
+                    let mut params = request
+                        .url()
+                        .query_pairs()
+                        .into_owned()
+                        .collect::<HashMap<_, _>>();
+                    params.extend(request.body_form::<HashMap<String, String>>().await?);
+                    // The older `/api` endpoints report their status in `resultCode`.
+                    let legacy = route.contains(" /api/");
+                    state.lock().unwrap().calls.push(Call { route, params });
+                    if body.get("meta").is_none() && !legacy {
+                        body["meta"] = meta(200)["meta"].clone();
+                    }
+                    json_response(body)
+                }
+                None => Response::new(StatusCode::NotFound),
+            }
+        }
+    })
+}
@@ -0,0 +1,263 @@
+use chrono::{DateTime, TimeZone, Utc};
+use ezviz::{
+    onvif::{discover_at, OnvifCamera},
+    transport::{Request, Response, Transport},
+    Error,
+};
+use futures::{future::BoxFuture, StreamExt};
+use smol::{block_on, net::UdpSocket};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const DEVICE_URL: &str = "http://192.168.1.20/onvif/device_service";
+const PASSWORD: &str = "ABCDEF";
+
+const PROBE_MATCH: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<SOAP-ENV:Envelope xmlns:SOAP-ENV="http://www.w3.org/2003/05/soap-envelope" xmlns:wsa="http://schemas.xmlsoap.org/ws/2004/08/addressing" xmlns:d="http://schemas.xmlsoap.org/ws/2005/04/discovery">
+<SOAP-ENV:Body><d:ProbeMatches><d:ProbeMatch>
+<wsa:EndpointReference><wsa:Address>urn:uuid:3fa1fe68-b915-4053-a3e1-bc23a4567890</wsa:Address></wsa:EndpointReference>
+<d:Types>dn:NetworkVideoTransmitter tds:Device</d:Types>
+<d:Scopes>onvif://www.onvif.org/type/video_encoder onvif://www.onvif.org/name/Front%20door onvif://www.onvif.org/hardware/CS-C6N</d:Scopes>
+<d:XAddrs>http://192.168.1.20/onvif/device_service</d:XAddrs>
+</d:ProbeMatch></d:ProbeMatches></SOAP-ENV:Body>
+</SOAP-ENV:Envelope>"#;
+
+/// A camera answering SOAP requests by the operation in their body.
+#[derive(Debug, Default)]
+struct MockCamera {
+    /// Operations received, in order.
+    operations: Mutex<Vec<String>>,
+    /// Number of upcoming pulls answered with a fault, as for an expired subscription.
+    failing_pulls: Mutex<usize>,
+}
+
+fn envelope(body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><env:Envelope xmlns:env="http://www.w3.org/2003/05/soap-envelope" xmlns:tt="http://www.onvif.org/ver10/schema" xmlns:wsnt="http://docs.oasis-open.org/wsn/b-2" xmlns:wsa="http://www.w3.org/2005/08/addressing"><env:Body>{}</env:Body></env:Envelope>"#,
+        body
+    )
+}
+
+fn fault(subcode: &str, reason: &str) -> String {
+    envelope(&format!(
+        r#"<env:Fault><env:Code><env:Value>env:Sender</env:Value><env:Subcode><env:Value>{}</env:Value></env:Subcode></env:Code><env:Reason><env:Text xml:lang="en">{}</env:Text></env:Reason></env:Fault>"#,
+        subcode, reason
+    ))
+}
+
+/// Checks the WS-Security password digest, and that it was created by the camera's
+/// clock rather than the test machine's.
+fn authenticated(body: &str) -> bool {
+    let field = |name: &str| {
+        let start = body.find(&format!("<{}", name))?;
+        let start = start + body[start..].find('>')? + 1;
+        Some(&body[start..start + body[start..].find('<')?])
+    };
+    let (digest, nonce, created) = match (field("Password"), field("Nonce"), field("Created")) {
+        (Some(digest), Some(nonce), Some(created)) => (digest, nonce, created),
+        _ => return false,
+    };
+    let mut expected = sha1::Sha1::new();
+    expected.update(&base64::decode(nonce).unwrap());
+    expected.update(created.as_bytes());
+    expected.update(PASSWORD.as_bytes());
+    let camera_time = Utc.ymd(2020, 12, 1).and_hms(12, 0, 0);
+    base64::encode(expected.digest().bytes()) == digest
+        && DateTime::parse_from_rfc3339(created)
+            .map(|created| {
+                (created.with_timezone(&Utc) - camera_time)
+                    .num_seconds()
+                    .abs()
+                    < 60
+            })
+            .unwrap_or(false)
+}
+
+impl MockCamera {
+    fn answer(&self, request: &Request) -> (u16, String) {
+        let body = String::from_utf8_lossy(&request.body);
+        let operation = body
+            .split("<s:Body><")
+            .nth(1)
+            .and_then(|rest| rest.split(&[' ', '>', '/'][..]).next())
+            .unwrap_or_default()
+            .to_owned();
+        self.operations.lock().unwrap().push(operation.clone());
+        if operation == "GetSystemDateAndTime" {
+            return (
+                200,
+                envelope(
+                    r#"<tds:GetSystemDateAndTimeResponse xmlns:tds="http://www.onvif.org/ver10/device/wsdl"><tds:SystemDateAndTime><tt:DateTimeType>NTP</tt:DateTimeType><tt:UTCDateTime><tt:Time><tt:Hour>12</tt:Hour><tt:Minute>0</tt:Minute><tt:Second>0</tt:Second></tt:Time><tt:Date><tt:Year>2020</tt:Year><tt:Month>12</tt:Month><tt:Day>1</tt:Day></tt:Date></tt:UTCDateTime></tds:SystemDateAndTime></tds:GetSystemDateAndTimeResponse>"#,
+                ),
+            );
+        }
+        if !authenticated(&body) {
+            return (400, fault("ter:NotAuthorized", "Sender not authorized"));
+        }
+        let response = match operation.as_str() {
+            "GetCapabilities" => r#"<tds:GetCapabilitiesResponse><tds:Capabilities><tt:Device><tt:XAddr>http://192.168.1.20/onvif/device_service</tt:XAddr></tt:Device><tt:Events><tt:XAddr>http://192.168.1.20/onvif/Events</tt:XAddr></tt:Events><tt:Media><tt:XAddr>http://192.168.1.20/onvif/Media</tt:XAddr></tt:Media></tds:Capabilities></tds:GetCapabilitiesResponse>"#.to_owned(),
+            "GetDeviceInformation" => r#"<tds:GetDeviceInformationResponse><tds:Manufacturer>EZVIZ</tds:Manufacturer><tds:Model>CS-C6N</tds:Model><tds:FirmwareVersion>V5.3.0 build 201027</tds:FirmwareVersion><tds:SerialNumber>D12345678</tds:SerialNumber><tds:HardwareId>88</tds:HardwareId></tds:GetDeviceInformationResponse>"#.to_owned(),
+            "GetProfiles" if request.url.ends_with("/Media") => r#"<trt:GetProfilesResponse><trt:Profiles fixed="true" token="Profile_1"><tt:Name>mainStream</tt:Name><tt:VideoEncoderConfiguration token="VideoEncoderToken_1"><tt:Name>VideoEncoder_1</tt:Name><tt:Resolution><tt:Width>1920</tt:Width><tt:Height>1080</tt:Height></tt:Resolution></tt:VideoEncoderConfiguration></trt:Profiles><trt:Profiles fixed="true" token="Profile_2"><tt:Name>subStream</tt:Name></trt:Profiles></trt:GetProfilesResponse>"#.to_owned(),
+            "GetStreamUri" => r#"<trt:GetStreamUriResponse><trt:MediaUri><tt:Uri>rtsp://192.168.1.20:554/Streaming/Channels/101?transportmode=unicast&amp;profile=Profile_1</tt:Uri></trt:MediaUri></trt:GetStreamUriResponse>"#.to_owned(),
+            "GetSnapshotUri" => r#"<trt:GetSnapshotUriResponse><trt:MediaUri><tt:Uri>http://192.168.1.20/onvif/snapshot</tt:Uri></trt:MediaUri></trt:GetSnapshotUriResponse>"#.to_owned(),
+            "CreatePullPointSubscription" if request.url.ends_with("/Events") => r#"<tev:CreatePullPointSubscriptionResponse><tev:SubscriptionReference><wsa:Address>http://192.168.1.20/onvif/Subscription?Idx=0</wsa:Address></tev:SubscriptionReference></tev:CreatePullPointSubscriptionResponse>"#.to_owned(),
+            "PullMessages" => {
+                let mut failing = self.failing_pulls.lock().unwrap();
+                if *failing > 0 {
+                    *failing -= 1;
+                    return (400, fault("ter:InvalidArgVal", "Subscription expired"));
+                }
+                r#"<tev:PullMessagesResponse>
+<wsnt:NotificationMessage><wsnt:Topic Dialect="http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet">tns1:RuleEngine/CellMotionDetector/Motion</wsnt:Topic><wsnt:Message><tt:Message UtcTime="2020-12-01T12:00:05Z" PropertyOperation="Changed"><tt:Source><tt:SimpleItem Name="VideoSourceConfigurationToken" Value="VideoSourceToken"/></tt:Source><tt:Data><tt:SimpleItem Name="IsMotion" Value="true"/></tt:Data></tt:Message></wsnt:Message></wsnt:NotificationMessage>
+<wsnt:NotificationMessage><wsnt:Topic Dialect="http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet">tns1:Device/Trigger/DigitalInput</wsnt:Topic><wsnt:Message><tt:Message UtcTime="2020-12-01T12:00:06Z"><tt:Data><tt:SimpleItem Name="LogicalState" Value="true"/></tt:Data></tt:Message></wsnt:Message></wsnt:NotificationMessage>
+<wsnt:NotificationMessage><wsnt:Topic Dialect="http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet">tns1:VideoSource/MotionAlarm</wsnt:Topic><wsnt:Message><tt:Message UtcTime="2020-12-01T12:00:09Z"><tt:Data><tt:SimpleItem Name="State" Value="false"/></tt:Data></tt:Message></wsnt:Message></wsnt:NotificationMessage>
+</tev:PullMessagesResponse>"#.to_owned()
+            }
+            _ => return (400, fault("ter:ActionNotSupported", "Unknown operation")),
+        };
+        (200, envelope(&response))
+    }
+}
+
+impl Transport for MockCamera {
+    fn send(&self, request: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        let (status, body) = self.answer(&request);
+        Box::pin(async move {
+            Ok(Response {
+                status,
+                headers: vec![],
+                body: body.into_bytes(),
+            })
+        })
+    }
+}
+
+fn camera(mock: &Arc<MockCamera>, password: &str) -> OnvifCamera {
+    OnvifCamera::new(DEVICE_URL, "admin", password).transport(mock.clone())
+}
+
+#[test]
+fn discovers_devices_answering_probes() {
+    block_on(async {
+        let camera = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = camera.local_addr().unwrap();
+        let responder = smol::spawn(async move {
+            let mut buf = vec![0; 4096];
+            let (len, from) = camera.recv_from(&mut buf).await.unwrap();
+            let probe = String::from_utf8_lossy(&buf[..len]).into_owned();
+            assert!(probe.contains("dn:NetworkVideoTransmitter"));
+            for answer in &[PROBE_MATCH, PROBE_MATCH] {
+                camera.send_to(answer.as_bytes(), from).await.unwrap();
+            }
+        });
+        let devices = discover_at(addr, Duration::from_millis(300)).await.unwrap();
+        responder.await;
+        assert_eq!(devices.len(), 1);
+        assert_eq!(devices[0].urls, vec![DEVICE_URL.to_owned()]);
+        assert_eq!(devices[0].name.as_deref(), Some("Front door"));
+        assert_eq!(devices[0].hardware.as_deref(), Some("CS-C6N"));
+        assert_eq!(devices[0].addr, addr.ip());
+    });
+}
+
+#[test]
+fn reads_device_information_and_media_uris() {
+    block_on(async {
+        let mock = Arc::new(MockCamera::default());
+        let camera = camera(&mock, PASSWORD);
+        assert!(!format!("{:?}", camera).contains(PASSWORD));
+        let info = camera.device_information().await.unwrap();
+        assert_eq!(info.manufacturer, "EZVIZ");
+        assert_eq!(info.serial_number, "D12345678");
+        let profiles = camera.profiles().await.unwrap();
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles[0].token, "Profile_1");
+        assert_eq!(profiles[0].name, "mainStream");
+        assert_eq!(
+            (profiles[0].width, profiles[0].height),
+            (Some(1920), Some(1080))
+        );
+        assert_eq!(profiles[1].width, None);
+        assert_eq!(
+            camera.stream_uri("Profile_1").await.unwrap(),
+            "rtsp://192.168.1.20:554/Streaming/Channels/101?transportmode=unicast&profile=Profile_1"
+        );
+        assert_eq!(
+            camera.snapshot_uri("Profile_1").await.unwrap(),
+            "http://192.168.1.20/onvif/snapshot"
+        );
+        // The clock and capabilities are only asked for once.
+        let operations = mock.operations.lock().unwrap();
+        assert_eq!(
+            operations
+                .iter()
+                .filter(|operation| *operation == "GetSystemDateAndTime"
+                    || *operation == "GetCapabilities")
+                .count(),
+            2
+        );
+    });
+}
+
+#[test]
+fn rejected_credentials_fail_with_invalid_credentials() {
+    block_on(async {
+        let mock = Arc::new(MockCamera::default());
+        assert!(matches!(
+            camera(&mock, "wrong").device_information().await,
+            Err(Error::InvalidCredentials)
+        ));
+        let camera = OnvifCamera::new(DEVICE_URL, "admin", PASSWORD).transport(RejectingCamera);
+        assert!(matches!(
+            camera.device_information().await,
+            Err(Error::InvalidCredentials)
+        ));
+    });
+}
+
+/// A camera that refuses every request with the HTTP status some devices use instead of
+/// a SOAP fault.
+#[derive(Debug)]
+struct RejectingCamera;
+
+impl Transport for RejectingCamera {
+    fn send(&self, _: Request) -> BoxFuture<'_, Result<Response, Error>> {
+        Box::pin(async {
+            Ok(Response {
+                status: 401,
+                headers: vec![],
+                body: vec![],
+            })
+        })
+    }
+}
+
+#[test]
+fn streams_motion_events_and_resubscribes() {
+    block_on(async {
+        let mock = Arc::new(MockCamera::default());
+        let camera = camera(&mock, PASSWORD);
+        let events = camera.motion_events();
+        futures::pin_mut!(events);
+        let first = events.next().await.unwrap().unwrap();
+        assert_eq!(first.topic, "tns1:RuleEngine/CellMotionDetector/Motion");
+        assert!(first.active);
+        assert_eq!(first.time.to_rfc3339(), "2020-12-01T12:00:05+00:00");
+        let second = events.next().await.unwrap().unwrap();
+        assert_eq!(second.topic, "tns1:VideoSource/MotionAlarm");
+        assert!(!second.active);
+        // A pull failing on an established subscription leads to a new one.
+        *mock.failing_pulls.lock().unwrap() = 1;
+        assert!(events.next().await.unwrap().unwrap().active);
+        let operations = mock.operations.lock().unwrap();
+        assert_eq!(
+            operations
+                .iter()
+                .filter(|operation| *operation == "CreatePullPointSubscription")
+                .count(),
+            2
+        );
+    });
+}